@@ -1,3 +1,10 @@
+use crate::config::UserConfig;
+use chrono::{DateTime, Local};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 #[derive(Debug)]
 pub enum InputCommand {
     Connect {
@@ -5,14 +12,196 @@ pub enum InputCommand {
         port: u16,
         nick: String,
         tls: bool,
+        password: Option<String>, // server password (PASS), never displayed
     },
     SendMessage {
         target: String,
         message: String,
     },
-    JoinChannel(String),
+    SendNotice {
+        target: String,
+        message: String,
+    },
+    JoinChannel(String, Option<String>), // channels, then their keys, each comma-separated
+    OpenQuery(String),                   // make a nick the target for plain messages
     PartChannel(String),
-    Quit,
+    SetAway(Option<String>), // None clears away status
+    AutoAway(bool),          // idle (true) or active again (false), from the UI
+    // Channel operator shortcuts; `channel: None` means the current channel.
+    Kick {
+        channel: Option<String>,
+        nick: String,
+        reason: Option<String>,
+    },
+    Ban {
+        channel: Option<String>,
+        mask: String,
+    },
+    Op {
+        channel: Option<String>,
+        nick: String,
+    },
+    Deop {
+        channel: Option<String>,
+        nick: String,
+    },
+    Voice {
+        channel: Option<String>,
+        nick: String,
+    },
+    Devoice {
+        channel: Option<String>,
+        nick: String,
+    },
+    Mode {
+        target: String,
+        args: Vec<String>, // modes and their parameters; empty queries the current modes
+    },
+    Whois(String),
+    Names(Option<String>),
+    ListChannels(Option<String>), // optional mask, e.g. *rust*
+    Ping,                         // measure lag to the server
+    Motd,                         // ask the server for its message of the day again
+    Ignore(Option<String>),       // hide a nick or mask; None lists the ignores
+    Unignore(String),
+    Raw(String),                // a protocol line sent as-is
+    Disconnect(Option<String>), // leave the server but keep meow running
+    Quit(Option<String>),
     SendPlainMessage(String),
-    Disconnected,
+    ReloadConfig(Box<UserConfig>),
+    Reconnect,
+    Kicked(String), // the server kicked us from this channel
+    Disconnected {
+        reconnect: bool,
+    },
+}
+
+/// Session state the IRC task keeps up to date for the UI to render.
+#[derive(Debug, Default)]
+pub struct Status {
+    pub channel: Option<String>,
+    pub nick: Option<String>,     // the nick the server actually gave us
+    pub hostmask: Option<String>, // our `nick!user@host` as others see it, learned from our own JOIN
+    pub topics: HashMap<String, String>,
+    pub members: HashMap<String, BTreeSet<String>>, // nicks in each joined channel
+    pub server: Option<String>,                     // set while connected
+    pub connecting: Option<(String, Instant)>, // the server a /connect is reaching, and since when
+    pub away: bool,
+    pub auto_away: bool,       // the away was set by auto_away_minutes, not /away
+    pub lag: Option<Duration>, // from the last /ping
+    pub invite: Option<String>, // the channel we were last invited to; a bare /join joins it
+}
+
+pub type SharedStatus = Arc<Mutex<Status>>;
+
+/// What a displayed line is, so the UI can style and filter it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Privmsg,
+    Notice,
+    Action, // CTCP ACTION, i.e. /me
+    Join,
+    Part,
+    Quit,
+    Nick,    // someone else changed nick; `sender` is the old one
+    Wallops, // operator announcements sent to every user with +w
+    System,  // status lines and server replies
+    Error,
+    Json, // an event for headless mode, already encoded; `text` is one JSON object
+}
+
+/// A line for the UI, sent from the IRC task instead of a preformatted
+/// string. For joins, parts and quits `text` is the whole description; for
+/// nick changes it's the new nick.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub timestamp: DateTime<Local>,
+    pub sender: Option<String>,
+    pub target: Option<String>, // channel or nick the line belongs to
+    pub kind: MessageKind,
+    pub text: String,
+    pub outgoing: bool, // something we sent, shown as `You`
+}
+
+impl Message {
+    pub fn new(kind: MessageKind, text: impl Into<String>) -> Self {
+        Message {
+            timestamp: Local::now(),
+            sender: None,
+            target: None,
+            kind,
+            text: text.into(),
+            outgoing: false,
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self::new(MessageKind::Error, text)
+    }
+
+    /// Sets who sent the line and the channel or conversation it belongs to.
+    pub fn with_sender(mut self, sender: &str, target: &str) -> Self {
+        self.sender = Some(sender.to_string());
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Marks the line as one we sent ourselves.
+    pub fn outgoing(mut self) -> Self {
+        self.outgoing = true;
+        self
+    }
+
+    /// `nick (#chan)` for channel lines, the bare nick otherwise. Our own
+    /// lines say `You`, and `You->nick` in private.
+    pub fn header(&self) -> String {
+        let sender = match self.outgoing {
+            true => "You",
+            false => self.sender.as_deref().unwrap_or("server"),
+        };
+        match &self.target {
+            Some(target) if self.in_channel() => format!("{} ({})", sender, target),
+            Some(target) if self.outgoing => format!("You->{}", target),
+            _ => sender.to_string(),
+        }
+    }
+
+    /// Whether the target is a channel rather than a private conversation.
+    pub fn in_channel(&self) -> bool {
+        self.target
+            .as_deref()
+            .is_some_and(|t| t.starts_with(['#', '&', '+', '!']))
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Self::new(MessageKind::System, text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Self::new(MessageKind::System, text)
+    }
+}
+
+/// The line as plain text, e.g. `<nick (#chan)> hi` or `*** nick has quit`.
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = self.header();
+        match self.kind {
+            MessageKind::Privmsg => write!(f, "<{}> {}", header, self.text),
+            MessageKind::Notice => write!(f, "-{}- {}", header, self.text),
+            MessageKind::Action => write!(f, "* {} {}", header, self.text),
+            MessageKind::Join | MessageKind::Part | MessageKind::Quit => {
+                write!(f, "*** {}", self.text)
+            }
+            MessageKind::Nick => write!(f, "*** {} is now {}", header, self.text),
+            MessageKind::Wallops => write!(f, "*** WALLOPS from {}: {}", header, self.text),
+            MessageKind::System | MessageKind::Error | MessageKind::Json => {
+                write!(f, "{}", self.text)
+            }
+        }
+    }
 }
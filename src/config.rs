@@ -1,58 +1,446 @@
-use crossterm::style::Color;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, Utc};
+use crossterm::style::{Color, Colored};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+use unicode_width::UnicodeWidthStr;
 
-#[derive(Debug, Deserialize, Clone)]
+/// What `meow --init` writes.
+const CONFIG_TEMPLATE: &str = include_str!("config_template.toml");
+
+/// Config file given with `--config`, overriding the usual locations.
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
 pub struct UserConfig {
     pub irc: Option<IrcConfig>,
     pub theme: Option<ThemeConfig>,
     pub emojis: Option<EmojiConfig>,
+    pub aliases: Option<AliasConfig>,
+    pub highlight: Option<HighlightConfig>,
+    pub ui: Option<UiConfig>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct ThemeConfig {
     pub background: Option<String>,
     pub foreground: Option<String>,
     pub accent: Option<String>,
     pub muted: Option<String>,
-    pub icons: Option<bool>, // ← moved here
+    pub preset: Option<String>, // "dark", "light" or "mono"; fills in colors left unset
+    pub icons: Option<bool>,    // ← moved here
+    pub prompt: Option<String>, // shown before the input line, e.g. "> "; "" for none
+    pub nick_colors: Option<Vec<String>>, // palette nicks are hashed into
+    pub own_nick_color: Option<String>, // our nick in echoes and when others mention it
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct IrcConfig {
+    pub server: Option<String>, // used by a bare /connect and by autoconnect
+    pub autoconnect: Option<bool>, // connect to `server` on startup
+    pub autojoin: Option<Vec<String>>, // channels joined whenever we connect to `server`
     pub nick: Option<String>,
+    pub alt_nicks: Option<Vec<String>>, // tried in order when the nick is taken
+    pub port: Option<u16>,
+    pub tls: Option<bool>,
+    pub accept_invalid_certs: Option<bool>, // skip TLS certificate verification, for private/lab servers
+    pub tls_ca_cert: Option<String>, // extra CA (or self-signed) certificate to trust, DER format
+    pub tls_client_cert: Option<String>, // PKCS#12 bundle (cert + key) for CertFP
+    pub tls_client_cert_pass: Option<String>, // password for that bundle, if it has one
+    pub server_password: Option<String>, // sent as PASS when connecting
+    pub command_timeout_secs: Option<u64>, // how long /whois, /names etc. wait for a reply
+    pub ping_interval_secs: Option<u64>, // send a PING after this long without hearing from the server
+    pub keepalive_secs: Option<u64>, // PING the server this often even while busy; unset or 0 is off
+    pub ping_timeout_secs: Option<u64>, // treat the connection as dead if that PING goes unanswered this long
+    pub flood_rate: Option<f64>,        // messages per second we send once the burst is used up
+    pub flood_burst: Option<u32>, // messages we may send back to back before being slowed down
+    pub quit_message: Option<String>,
+    pub reconnect_on_error: Option<bool>, // whether a server ERROR triggers auto-reconnect
+    pub reconnect_base_secs: Option<u64>, // delay before the first retry; grows by this much each attempt
+    pub reconnect_max_secs: Option<u64>,  // upper bound on the delay between retries
+    pub reconnect_max_attempts: Option<u32>, // give up after this many retries (unset retries forever)
+    pub log_dir: Option<String>,             // write a plain-text log of each session here
+    pub ctcp_replies: Option<bool>, // answer CTCP VERSION, PING and TIME; off keeps the client private
+    pub ctcp_version: Option<String>, // what we answer CTCP VERSION with
+    pub show_ctcp: Option<bool>,    // show CTCP requests (and whether we answered) in the buffer
+    pub autojoin_on_invite: Option<bool>, // join channels we're invited to
+    pub invite_from: Option<Vec<String>>, // only auto-join invites from these nicks or masks
+    pub show_motd: Option<bool>, // false keeps the MOTD out of the buffer on connect; /motd still shows it
+    pub rejoin_on_kick: Option<bool>, // rejoin a channel we're kicked from after a short delay
+    pub rejoin_max_attempts: Option<u32>, // kicks in a row after which we stay out (default 3)
+    pub ignore: Option<Vec<String>>, // nicks or masks like `baduser!*@*` to start out ignoring
+    pub proxy: Option<ProxyConfig>, // dial the server through a SOCKS5 proxy
+    pub profiles: Option<HashMap<String, ServerProfile>>, // named networks, e.g. [irc.profiles.libera]
+}
+
+/// The usual IRC port: 6697 for TLS, 6667 for plain text. Used when
+/// neither `/connect` nor the config names one.
+pub fn default_port(tls: bool) -> u16 {
+    if tls {
+        6697
+    } else {
+        6667
+    }
+}
+
+/// A named network under `[irc.profiles.<name>]`, used by `/connect <name>`.
+/// Unset fields fall back to the plain `[irc]` values.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ServerProfile {
+    pub server: Option<String>,
     pub port: Option<u16>,
+    pub nick: Option<String>,
     pub tls: Option<bool>,
+    pub server_password: Option<String>,
+}
+
+/// A SOCKS5 proxy under `[irc.proxy]`, e.g. Tor on 127.0.0.1:9050.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub host: String,
+    pub port: Option<u16>, // defaults to 1080
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct UiConfig {
+    pub pause_scroll_while_typing: Option<bool>, // hold the view still while the input line has text
+    pub hide_joins: Option<bool>,                // leave join/part/quit lines out of the display
+    pub hanging_indent: Option<bool>, // line wrapped messages up under their text, not the nick
+    pub skip_welcome: Option<bool>,   // start straight in the client, without the welcome screens
+    pub ascii: Option<bool>, // plain ASCII borders and no icons; unset guesses from TERM and the locale
+    pub strip_formatting: Option<bool>, // drop colors and bold etc. from others' messages
+    pub hyperlinks: Option<bool>, // make links clickable with OSC 8; turn off if the terminal shows junk
+    pub scroll_step: Option<ScrollStep>, // how far PgUp/PgDn move: lines, "half" or "page"
+    pub mouse_scroll: Option<bool>, // scroll with the mouse wheel; stops the terminal selecting text
+    pub expand_incoming_emojis: Option<bool>, // show others' :alias: shortcodes as emoji too
+    pub join_pastes: Option<bool>, // paste multi-line text as one line instead of one message per line
+    pub paste_confirm_lines: Option<usize>, // ask before sending a paste with more lines than this
+    pub history_size: Option<usize>, // input lines kept in ~/.meow/history
+    pub timestamp_format: Option<String>, // strftime format, e.g. "%H:%M"; set to show timestamps
+    pub timestamps_utc: Option<bool>, // show (and log) times in UTC instead of local time
+    pub watch_config: Option<bool>, // reload the config by itself whenever the file changes
+    pub auto_away_minutes: Option<u64>, // mark us away after this long without a keypress
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct HighlightConfig {
+    pub words: Option<Vec<HighlightWord>>, // extra words that highlight a line besides your nick
+    pub notifications: Option<bool>, // desktop notification for highlights while the terminal is in the background
+}
+
+/// An entry in `[highlight] words`: a plain word, or a table saying what a
+/// match does, e.g. `{ word = "deploy*", bell = false }`. Words are matched
+/// like ignore masks, `*` for any run of characters and `?` for any one, and
+/// always against a whole word of the line.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum HighlightWord {
+    Word(String),
+    Rule {
+        word: String,
+        bell: Option<bool>,   // ring the terminal bell (default true)
+        notify: Option<bool>, // desktop notification; defaults to `notifications`
+    },
+}
+
+impl HighlightWord {
+    pub fn word(&self) -> &str {
+        match self {
+            HighlightWord::Word(word) | HighlightWord::Rule { word, .. } => word,
+        }
+    }
+
+    pub fn bell(&self) -> bool {
+        match self {
+            HighlightWord::Word(_) => true,
+            HighlightWord::Rule { bell, .. } => bell.unwrap_or(true),
+        }
+    }
+
+    /// Whether a match notifies, given the `notifications` setting.
+    pub fn notify(&self, default: bool) -> bool {
+        match self {
+            HighlightWord::Word(_) => default,
+            HighlightWord::Rule { notify, .. } => notify.unwrap_or(default),
+        }
+    }
+}
+
+/// How far PgUp/PgDn scroll: a number of lines, or `"half"` or `"page"`
+/// for half or all of the visible buffer.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum ScrollStep {
+    Lines(usize),
+    Fraction(PageFraction),
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFraction {
+    Half,
+    Page,
+}
+
+impl Default for ScrollStep {
+    fn default() -> Self {
+        ScrollStep::Lines(5)
+    }
+}
+
+impl ScrollStep {
+    /// Lines to move with `height` lines on screen. Never more than that, so
+    /// a step can't skip anything, and never less than one.
+    pub fn lines(self, height: usize) -> usize {
+        let lines = match self {
+            ScrollStep::Lines(lines) => lines,
+            ScrollStep::Fraction(PageFraction::Half) => height / 2,
+            ScrollStep::Fraction(PageFraction::Page) => height,
+        };
+        lines.min(height).max(1)
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, PartialEq)]
 pub struct EmojiConfig {
     #[serde(flatten)]
-    pub aliases: std::collections::HashMap<String, String>,
+    pub aliases: HashMap<String, String>,
 }
 
-impl Default for UserConfig {
-    fn default() -> Self {
-        UserConfig {
-            irc: None,
-            theme: None,
-            emojis: None,
+/// How many aliases deep an expansion may go before it's taken to be a loop.
+const ALIAS_DEPTH: usize = 10;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct AliasConfig {
+    #[serde(flatten)]
+    pub aliases: HashMap<String, String>,
+}
+
+impl AliasConfig {
+    /// Expands a leading `/alias` in `input` into its definition, with the
+    /// rest of `input` appended. Definitions may use other aliases; `None`
+    /// means they went more than `ALIAS_DEPTH` deep, which is surely a loop.
+    pub fn expand(&self, input: &str) -> Option<String> {
+        let mut line = input.to_string();
+        let mut depth = 0;
+        loop {
+            let Some(rest) = line.strip_prefix('/') else {
+                return Some(line);
+            };
+            let (name, args) = rest.split_once(' ').unwrap_or((rest, ""));
+            let Some(definition) = self.aliases.get(name) else {
+                return Some(line);
+            };
+            if depth == ALIAS_DEPTH {
+                return None;
+            }
+            depth += 1;
+            line = if args.is_empty() {
+                definition.clone()
+            } else {
+                format!("{} {}", definition.trim_end(), args)
+            };
+        }
+    }
+}
+
+impl EmojiConfig {
+    /// Replaces `:alias:` shortcodes with their emoji in one pass, so an emoji
+    /// that itself looks like a shortcode isn't expanded again. Words that
+    /// look like URLs are left alone.
+    pub fn expand(&self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        for (i, word) in text.split(' ').enumerate() {
+            if i > 0 {
+                out.push(' ');
+            }
+            if word.contains("://") || word.starts_with("www.") {
+                out.push_str(word);
+                continue;
+            }
+            let mut rest = word;
+            while let Some(start) = rest.find(':') {
+                out.push_str(&rest[..start]);
+                let after = &rest[start + 1..];
+                let shortcode = after
+                    .find(':')
+                    .and_then(|end| Some((end, self.aliases.get(&after[..end])?)));
+                match shortcode {
+                    Some((end, emoji)) => {
+                        out.push_str(emoji);
+                        rest = &after[end + 1..];
+                    }
+                    None => {
+                        out.push(':');
+                        rest = after;
+                    }
+                }
+            }
+            out.push_str(rest);
         }
+        out
     }
 }
 
+/// How long the config file has to stay unchanged before it's reloaded, so an
+/// editor's save (often a truncate, a write and a rename) is seen as one change.
+const WATCH_SETTLE: Duration = Duration::from_millis(300);
+
+/// Notices the config file changing on disk, for `ui.watch_config`. It
+/// compares modification times each time it's asked, which the UI does a few
+/// times a second, rather than relying on platform file events.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    seen: Option<SystemTime>,    // modification time last looked at
+    changed_at: Option<Instant>, // when that last differed, until reported
+}
+
+impl ConfigWatcher {
+    pub fn new() -> Self {
+        let path = UserConfig::config_path();
+        let seen = modified(&path);
+        ConfigWatcher {
+            path,
+            seen,
+            changed_at: None,
+        }
+    }
+
+    /// Whether the file changed and has since been left alone for a moment.
+    /// Each change is reported once.
+    pub fn changed(&mut self) -> bool {
+        let now = modified(&self.path);
+        if now != self.seen {
+            self.seen = now;
+            self.changed_at = Some(Instant::now());
+            return false;
+        }
+        match self.changed_at {
+            Some(at) if at.elapsed() >= WATCH_SETTLE => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 impl UserConfig {
     pub fn load() -> Option<Self> {
+        Self::read().ok().flatten()
+    }
+
+    /// Whether to draw with plain ASCII instead of box-drawing characters and
+    /// icons. Unless `ui.ascii` says, it's on for the Linux console, a dumb
+    /// terminal, or a locale that isn't UTF-8.
+    pub fn ascii(&self) -> bool {
+        if let Some(ascii) = self.ui.as_ref().and_then(|u| u.ascii) {
+            return ascii;
+        }
+        let term = std::env::var("TERM").unwrap_or_default();
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()));
+        let utf8 = locale.map_or(cfg!(windows), |l| {
+            let l = l.to_ascii_lowercase();
+            l.contains("utf-8") || l.contains("utf8")
+        });
+        term == "linux" || term == "dumb" || !utf8
+    }
+
+    /// Like `load`, but reports why an existing config couldn't be used.
+    /// A missing file is `Ok(None)`.
+    pub fn read() -> anyhow::Result<Option<Self>> {
         let path = Self::config_path();
         if !path.exists() {
-            return None;
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        let mut config: UserConfig = toml::from_str(&contents)?;
+        if let Some(theme) = config.theme.as_mut() {
+            theme.apply_preset();
         }
-        let contents = fs::read_to_string(&path).ok()?;
-        toml::from_str(&contents).ok()
+        Ok(Some(config))
     }
 
-    fn config_path() -> PathBuf {
+    /// Names of the top-level sections that differ between two configs.
+    pub fn changed_sections(&self, other: &UserConfig) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.irc != other.irc {
+            changed.push("irc");
+        }
+        if self.theme != other.theme {
+            changed.push("theme");
+        }
+        if self.emojis != other.emojis {
+            changed.push("emojis");
+        }
+        if self.aliases != other.aliases {
+            changed.push("aliases");
+        }
+        if self.highlight != other.highlight {
+            changed.push("highlight");
+        }
+        if self.ui != other.ui {
+            changed.push("ui");
+        }
+        changed
+    }
+
+    /// Writes a commented starter config to `config_path`, creating its
+    /// directory. Won't replace an existing file unless `force` is set.
+    pub fn write_template(force: bool) -> anyhow::Result<PathBuf> {
+        let path = Self::config_path();
+        if path.exists() && !force {
+            anyhow::bail!(
+                "{} already exists; pass --force to overwrite it",
+                path.display()
+            );
+        }
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, CONFIG_TEMPLATE)?;
+        Ok(path)
+    }
+
+    /// Reads the config from `path` instead, for `--config`. Only the first
+    /// call counts.
+    pub fn set_path(path: PathBuf) {
+        let _ = CONFIG_PATH.set(path);
+    }
+
+    /// Where the config lives: `--config`, then `$MEOW_CONFIG`, then
+    /// `$XDG_CONFIG_HOME/meow/config.toml` if that exists, then the per-OS
+    /// default.
+    pub fn config_path() -> PathBuf {
+        if let Some(path) = CONFIG_PATH.get() {
+            return path.clone();
+        }
+        if let Some(path) = std::env::var_os("MEOW_CONFIG").filter(|p| !p.is_empty()) {
+            return PathBuf::from(path);
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            if let Some(xdg) = std::env::var_os("XDG_CONFIG_HOME").filter(|p| !p.is_empty()) {
+                let path = PathBuf::from(xdg).join("meow").join("config.toml");
+                if path.exists() {
+                    return path;
+                }
+            }
+        }
+
         #[cfg(target_os = "windows")]
         {
             // Use %USERPROFILE%\meowconf\config.toml
@@ -74,14 +462,329 @@ impl UserConfig {
     }
 }
 
-pub fn parse_color(hex: &str) -> Option<Color> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() == 6 {
-        let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
-        let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-        let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
-        Some(Color::Rgb { r, g, b })
+/// Parses a theme color: `#rrggbb`, shorthand `#rgb` (the `#` is optional
+/// for both), an entry of the 256-color palette as `ansi:<0-255>`, or one of
+/// the terminal's sixteen named colors, e.g. `red`, `cyan` or `brightblue`.
+/// Names follow the usual ANSI meaning, so `white` is the light grey and
+/// `brightwhite` is white. Palette colors look right on terminals without
+/// truecolor, where hex is only approximated.
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(index) = value.strip_prefix("ansi:") {
+        return index.trim().parse().ok().map(Color::AnsiValue);
+    }
+    let named = match value.to_ascii_lowercase().as_str() {
+        "black" => Some(Color::Black),
+        "red" => Some(Color::DarkRed),
+        "green" => Some(Color::DarkGreen),
+        "yellow" => Some(Color::DarkYellow),
+        "blue" => Some(Color::DarkBlue),
+        "magenta" | "purple" => Some(Color::DarkMagenta),
+        "cyan" => Some(Color::DarkCyan),
+        "white" | "grey" | "gray" => Some(Color::Grey),
+        "brightblack" | "darkgrey" | "darkgray" => Some(Color::DarkGrey),
+        "brightred" => Some(Color::Red),
+        "brightgreen" => Some(Color::Green),
+        "brightyellow" => Some(Color::Yellow),
+        "brightblue" => Some(Color::Blue),
+        "brightmagenta" => Some(Color::Magenta),
+        "brightcyan" => Some(Color::Cyan),
+        "brightwhite" => Some(Color::White),
+        _ => None,
+    };
+    if named.is_some() {
+        return named;
+    }
+
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).ok();
+    match hex.len() {
+        6 => Some(Color::Rgb {
+            r: channel(&hex[0..2])?,
+            g: channel(&hex[2..4])?,
+            b: channel(&hex[4..6])?,
+        }),
+        // Each shorthand digit is doubled: #f0a is #ff00aa.
+        3 => Some(Color::Rgb {
+            r: channel(&hex[0..1])? * 17,
+            g: channel(&hex[1..2])? * 17,
+            b: channel(&hex[2..3])? * 17,
+        }),
+        _ => None,
+    }
+}
+
+/// Colors a theme preset fills in. `None` keeps the terminal's own.
+pub struct ThemePreset {
+    pub background: Option<&'static str>,
+    pub foreground: Option<&'static str>,
+    pub accent: &'static str,
+    pub muted: &'static str,
+}
+
+pub const DARK_PRESET: ThemePreset = ThemePreset {
+    background: Some("#1e1e2e"),
+    foreground: Some("#cdd6f4"),
+    accent: "#cba6f7",
+    muted: "#6c7086",
+};
+
+pub const LIGHT_PRESET: ThemePreset = ThemePreset {
+    background: Some("#fafafa"),
+    foreground: Some("#383a42"),
+    accent: "#a626a4",
+    muted: "#a0a1a7",
+};
+
+pub const MONO_PRESET: ThemePreset = ThemePreset {
+    background: None,
+    foreground: Some("#d0d0d0"),
+    accent: "#ffffff",
+    muted: "#808080",
+};
+
+fn theme_preset(name: &str) -> Option<&'static ThemePreset> {
+    match name.to_ascii_lowercase().as_str() {
+        "dark" => Some(&DARK_PRESET),
+        "light" => Some(&LIGHT_PRESET),
+        "mono" => Some(&MONO_PRESET),
+        _ => None,
+    }
+}
+
+impl ThemeConfig {
+    /// Fills colors that weren't set (or were left empty) from `preset`, so
+    /// an explicit color always wins and anything still unset falls back to
+    /// the built-in defaults.
+    fn apply_preset(&mut self) {
+        let Some(preset) = self.preset.as_deref().and_then(theme_preset) else {
+            return;
+        };
+        let fill = |field: &mut Option<String>, value: Option<&str>| {
+            if field.as_deref().is_none_or(str::is_empty) {
+                if let Some(value) = value {
+                    *field = Some(value.to_string());
+                }
+            }
+        };
+        fill(&mut self.background, preset.background);
+        fill(&mut self.foreground, preset.foreground);
+        fill(&mut self.accent, Some(preset.accent));
+        fill(&mut self.muted, Some(preset.muted));
+    }
+
+    /// A message for each color in the theme that `parse_color` can't read
+    /// (and an unknown preset), naming the key, so a typo doesn't just
+    /// silently fall back to the default.
+    pub fn problems(&self) -> Vec<String> {
+        let mut colors: Vec<(String, &str)> = [
+            ("background", &self.background),
+            ("foreground", &self.foreground),
+            ("accent", &self.accent),
+            ("muted", &self.muted),
+            ("own_nick_color", &self.own_nick_color),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.to_string(), value.as_deref()?)))
+        .collect();
+        for (i, value) in self.nick_colors.iter().flatten().enumerate() {
+            colors.push((format!("nick_colors[{}]", i), value));
+        }
+        let mut problems: Vec<String> = colors
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty() && parse_color(value).is_none())
+            .map(|(key, value)| {
+                format!(
+                    "theme.{}: \"{}\" isn't a color (use #rrggbb, #rgb, ansi:0-255 or a name like cyan)",
+                    key, value
+                )
+            })
+            .collect();
+        if let Some(preset) = self.preset.as_deref() {
+            if theme_preset(preset).is_none() {
+                problems.push(format!(
+                    "theme.preset: \"{}\" isn't a preset (use dark, light or mono)",
+                    preset
+                ));
+            }
+        }
+        if let Some(prompt) = self.prompt.as_deref() {
+            if let Err(problem) = check_prompt(prompt) {
+                problems.push(format!("theme.prompt: \"{}\" {}", prompt, problem));
+            }
+        }
+        problems
+    }
+
+    /// The input prompt: `prompt` when it's usable, otherwise the default.
+    pub fn prompt(&self) -> &str {
+        self.prompt
+            .as_deref()
+            .filter(|p| check_prompt(p).is_ok())
+            .unwrap_or(DEFAULT_PROMPT)
+    }
+
+    /// The color for our own nick, if the theme picks one.
+    pub fn own_nick_color(&self) -> Option<Color> {
+        self.own_nick_color.as_deref().and_then(parse_color)
+    }
+}
+
+/// The input prompt when the theme doesn't set one.
+pub const DEFAULT_PROMPT: &str = "❯ ";
+
+/// Widest prompt allowed, in columns, so the input still has room.
+const MAX_PROMPT_WIDTH: usize = 16;
+
+/// Why `prompt` can't be used, if it can't: control characters would throw
+/// off the wrapping math, and a very wide one leaves no room to type.
+fn check_prompt(prompt: &str) -> Result<(), String> {
+    if prompt.chars().any(char::is_control) {
+        return Err("has control characters".to_string());
+    }
+    if prompt.width() > MAX_PROMPT_WIDTH {
+        return Err(format!("is wider than {} columns", MAX_PROMPT_WIDTH));
+    }
+    Ok(())
+}
+
+/// The timestamp format used when `timestamp_format` can't be.
+pub const DEFAULT_TIME_FORMAT: &str = "%H:%M";
+
+impl UiConfig {
+    /// The format for line timestamps, or `None` when they're off. A format
+    /// chrono can't read falls back to the default; `problems` says so.
+    pub fn timestamp_format(&self) -> Option<&str> {
+        let format = self.timestamp_format.as_deref()?;
+        Some(if valid_time_format(format) {
+            format
+        } else {
+            DEFAULT_TIME_FORMAT
+        })
+    }
+
+    pub fn timestamps_utc(&self) -> bool {
+        self.timestamps_utc == Some(true)
+    }
+
+    /// A message for each setting that can't be used as written.
+    pub fn problems(&self) -> Vec<String> {
+        match self.timestamp_format.as_deref() {
+            Some(format) if !valid_time_format(format) => vec![format!(
+                "ui.timestamp_format: \"{}\" isn't a valid strftime format, using \"{}\"",
+                format, DEFAULT_TIME_FORMAT
+            )],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn valid_time_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+/// `time` written out with `format`, in UTC or local time.
+pub fn format_time(time: &DateTime<Local>, format: &str, utc: bool) -> String {
+    if utc {
+        time.with_timezone(&Utc).format(format).to_string()
     } else {
-        None
+        time.format(format).to_string()
+    }
+}
+
+/// Default color for notices and other low-key lines.
+pub const DEFAULT_MUTED: &str = "38;2;128;128;128";
+
+/// SGR parameters selecting `color` as the foreground, or `default` when the
+/// theme doesn't set one.
+pub fn fg_code(color: Option<Color>, default: &str) -> String {
+    match color {
+        Some(color) => Colored::ForegroundColor(color).to_string(),
+        None => default.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_color_form() {
+        let cases = [
+            (
+                "#ff8000",
+                Some(Color::Rgb {
+                    r: 255,
+                    g: 128,
+                    b: 0,
+                }),
+            ),
+            (
+                "#F0A",
+                Some(Color::Rgb {
+                    r: 255,
+                    g: 0,
+                    b: 170,
+                }),
+            ),
+            (
+                "ff8000",
+                Some(Color::Rgb {
+                    r: 255,
+                    g: 128,
+                    b: 0,
+                }),
+            ),
+            ("0a0", Some(Color::Rgb { r: 0, g: 170, b: 0 })),
+            ("ansi:0", Some(Color::AnsiValue(0))),
+            ("ansi:255", Some(Color::AnsiValue(255))),
+            ("ansi:256", None),
+            ("ansi:-1", None),
+            ("ansi:", None),
+            ("red", Some(Color::DarkRed)),
+            ("BrightBlue", Some(Color::Blue)),
+            (" gray ", Some(Color::Grey)),
+            ("", None),
+            ("nope", None),
+            ("#12", None),
+            ("#gggggg", None),
+            ("#ff80001", None),
+            ("rgb(1,2,3)", None),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_color(value), expected, "parse_color({:?})", value);
+        }
+    }
+
+    #[test]
+    fn stray_irc_keys_leave_the_rest_of_the_config_alone() {
+        let config: UserConfig = toml::from_str(
+            r#"
+            [irc]
+            nick = "mycat"
+            nickk = "typo"
+
+            [irc.profiles.libera]
+            server = "irc.libera.chat"
+            port = 6697
+            "#,
+        )
+        .unwrap();
+        let irc = config.irc.unwrap();
+        assert_eq!(irc.nick.as_deref(), Some("mycat"));
+        let libera = &irc.profiles.unwrap()["libera"];
+        assert_eq!(libera.server.as_deref(), Some("irc.libera.chat"));
+        assert_eq!(libera.port, Some(6697));
+    }
+
+    #[test]
+    fn reload_reports_changed_aliases() {
+        let before: UserConfig = toml::from_str("[aliases]\nj = \"/join\"").unwrap();
+        let after: UserConfig = toml::from_str("[aliases]\nj = \"/join #meow\"").unwrap();
+        assert_eq!(after.changed_sections(&before), vec!["aliases"]);
+        assert!(before.changed_sections(&before).is_empty());
     }
 }
@@ -0,0 +1,121 @@
+use crate::app::{InputCommand, Message, MessageKind};
+use crate::config::UserConfig;
+use crate::irc_client::server_time;
+use crate::logger::strip_formatting;
+use crate::ui::{parse_input, Parsed};
+use chrono::Utc;
+use irc::client::prelude::Command;
+use serde_json::json;
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::select;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// Turns an incoming IRC message into a single JSON line with the event
+/// type, channel, sender, text and a UTC timestamp (the server's own, when
+/// it tags the message with one).
+pub fn event_json(message: &irc::client::prelude::Message) -> String {
+    let sender = message.source_nickname();
+    let (event, channel, text): (&str, Option<&str>, Option<String>) = match &message.command {
+        Command::PRIVMSG(target, text) => ("privmsg", Some(target), Some(text.clone())),
+        Command::NOTICE(target, text) => ("notice", Some(target), Some(text.clone())),
+        Command::JOIN(channel, _, _) => ("join", Some(channel), None),
+        Command::PART(channel, reason) => ("part", Some(channel), reason.clone()),
+        Command::QUIT(reason) => ("quit", None, reason.clone()),
+        Command::KICK(channel, nick, reason) => (
+            "kick",
+            Some(channel),
+            Some(format!("{} {}", nick, reason.as_deref().unwrap_or(""))),
+        ),
+        Command::TOPIC(channel, topic) => ("topic", Some(channel), topic.clone()),
+        Command::NICK(nick) => ("nick", None, Some(nick.clone())),
+        Command::WALLOPS(text) => ("wallops", None, Some(text.clone())),
+        Command::ERROR(e) => ("error", None, Some(e.clone())),
+        _ => (
+            "raw",
+            None,
+            Some(message.to_string().trim_end().to_string()),
+        ),
+    };
+    json!({
+        "event": event,
+        "channel": channel,
+        "sender": sender,
+        "text": text,
+        "timestamp": server_time(message)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(Utc::now)
+            .to_rfc3339(),
+    })
+    .to_string()
+}
+
+/// One JSON line for a line from the IRC task: JSON events pass through,
+/// anything else becomes a `status` (or `error`) event.
+fn event_line(line: Message) -> String {
+    if line.kind == MessageKind::Json {
+        return line.text;
+    }
+    json!({
+        "event": if line.kind == MessageKind::Error { "error" } else { "status" },
+        "text": strip_formatting(&line.to_string()),
+        "timestamp": line.timestamp.with_timezone(&Utc).to_rfc3339(),
+    })
+    .to_string()
+}
+
+/// Runs meow without the terminal UI: commands come from stdin, parsed just
+/// like the interactive client's, and every event is written to stdout as
+/// newline-delimited JSON.
+pub async fn run_headless(
+    input_tx: Sender<InputCommand>,
+    mut irc_rx: Receiver<Message>,
+) -> anyhow::Result<()> {
+    let config = UserConfig::load().unwrap_or_default();
+    let mut stdin = BufReader::new(io::stdin()).lines();
+    let mut stdout = io::stdout();
+    let mut stdin_open = true;
+
+    loop {
+        select! {
+            line = stdin.next_line(), if stdin_open => {
+                match line? {
+                    Some(line) => {
+                        // The same commands as the interactive client, which echoes them itself.
+                        let error = match parse_input(line.trim(), Some(&config)) {
+                            Parsed::Send(commands, _) => {
+                                for command in commands {
+                                    input_tx.send(command).await?;
+                                }
+                                None
+                            }
+                            Parsed::Invalid(error, _) => Some(error),
+                            Parsed::Local { cmd, .. } => {
+                                Some(format!("{} is only available in the interactive client", cmd))
+                            }
+                        };
+                        if let Some(error) = error {
+                            let out = event_line(Message::error(error));
+                            stdout.write_all(out.as_bytes()).await?;
+                            stdout.write_all(b"\n").await?;
+                            stdout.flush().await?;
+                        }
+                    }
+                    None => {
+                        // End of input: leave the server cleanly.
+                        stdin_open = false;
+                        input_tx.send(InputCommand::Quit(None)).await?;
+                    }
+                }
+            }
+            maybe_line = irc_rx.recv() => {
+                let Some(line) = maybe_line else { break };
+                let out = event_line(line);
+                stdout.write_all(out.as_bytes()).await?;
+                stdout.write_all(b"\n").await?;
+                stdout.flush().await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use crate::config::UserConfig;
+use std::fs;
+use std::path::PathBuf;
+
+/// How many input lines are kept when `history_size` isn't set.
+pub const DEFAULT_SIZE: usize = 500;
+
+/// Input history lives next to the config file, e.g. `~/.meow/history`.
+fn history_path() -> PathBuf {
+    UserConfig::config_path().with_file_name("history")
+}
+
+/// Lines that shouldn't be written to disk: a `/connect` with a password,
+/// a `/join` with a channel key, identifying to NickServ, or a raw `PASS`.
+fn is_secret(line: &str) -> bool {
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some("/connect") => parts.count() >= 5,
+        Some("/join") => parts.count() >= 2,
+        Some("/msg") => {
+            let target = parts.next().unwrap_or("");
+            let command = parts.next().unwrap_or("");
+            target.eq_ignore_ascii_case("nickserv") && command.eq_ignore_ascii_case("identify")
+        }
+        Some("/raw") => parts
+            .next()
+            .is_some_and(|command| command.eq_ignore_ascii_case("pass")),
+        _ => false,
+    }
+}
+
+/// Loads the last `limit` lines of input history, oldest first. A missing
+/// or unreadable file just means starting with no history.
+pub fn load(limit: usize) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(history_path()) else {
+        return Vec::new();
+    };
+    let lines: Vec<String> = contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(str::to_string)
+        .collect();
+    lines[lines.len().saturating_sub(limit)..].to_vec()
+}
+
+/// Writes the last `limit` lines of `history` back out, leaving out anything
+/// with a password in it and repeats of the line before.
+pub fn save(history: &[String], limit: usize) -> std::io::Result<()> {
+    let mut kept: Vec<&str> = Vec::new();
+    for line in history.iter().filter(|l| !is_secret(l)) {
+        if kept.last() != Some(&line.as_str()) {
+            kept.push(line);
+        }
+    }
+    let kept = &kept[kept.len().saturating_sub(limit)..];
+
+    let path = history_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut contents = kept.join("\n");
+    contents.push('\n');
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passwords_stay_out_of_history() {
+        assert!(is_secret(
+            "/connect irc.libera.chat 6697 mycat true hunter2"
+        ));
+        assert!(is_secret("/join #secret hunter2"));
+        assert!(is_secret("/msg NickServ IDENTIFY hunter2"));
+        assert!(is_secret("/msg nickserv identify mycat hunter2"));
+        assert!(is_secret("/raw PASS hunter2"));
+        assert!(is_secret("/raw pass hunter2"));
+        assert!(!is_secret("/connect irc.libera.chat 6697 mycat"));
+        assert!(!is_secret("/join #meow"));
+        assert!(!is_secret("/msg NickServ INFO mycat"));
+        assert!(!is_secret("/msg alice identify yourself"));
+        assert!(!is_secret("/raw PRIVMSG #meow :hi"));
+    }
+}
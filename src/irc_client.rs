@@ -1,51 +1,877 @@
-use crate::app::InputCommand;
-use crate::config::{parse_color, UserConfig};
+use crate::app::{self, InputCommand, MessageKind, SharedStatus};
+use crate::config::{default_port, EmojiConfig, UserConfig};
+use crate::headless;
+use crate::logger::expand_home;
+use crate::wildcard::wildcard_match;
 use anyhow::Result;
+use chrono::{DateTime, Local};
 use futures_util::stream::StreamExt;
 use irc::client::prelude::*;
+use std::collections::{HashMap, VecDeque};
 use std::sync::Arc;
 use tokio::select;
 use tokio::sync::{
-    mpsc::{Receiver, Sender},
+    mpsc::{self, Receiver, Sender},
     Mutex,
 };
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+
+/// A command that is waiting on a reply from the server.
+struct PendingReply {
+    command: &'static str,
+    replies: &'static [Response],
+    deadline: Instant,
+}
+
+/// Requests still waiting on a reply. Shared with the listener task so it can
+/// clear entries as the matching numerics arrive.
+type PendingReplies = Arc<std::sync::Mutex<Vec<PendingReply>>>;
+
+/// When each `/ping` was sent, keyed by the token the server echoes back.
+type PingTimes = Arc<std::sync::Mutex<HashMap<String, Instant>>>;
+
+/// Masks whose messages and notices are dropped. Shared with the listener
+/// so `/ignore` takes effect on the running connection.
+type IgnoreList = Arc<std::sync::Mutex<Vec<String>>>;
+
+/// Fills out a partial mask: `nick` means `nick!*@*` and `user@host` means
+/// `*!user@host`.
+fn ignore_mask(mask: &str) -> String {
+    match (mask.contains('!'), mask.contains('@')) {
+        (false, false) => format!("{}!*@*", mask),
+        (false, true) => format!("*!{}", mask),
+        (true, false) => format!("{}@*", mask),
+        (true, true) => mask.to_string(),
+    }
+}
+
+/// Whether `message` comes from someone on the ignore list.
+fn is_ignored(ignores: &IgnoreList, message: &Message) -> bool {
+    let Some(Prefix::Nickname(nick, user, host)) = &message.prefix else {
+        return false;
+    };
+    let source = format!("{}!{}@{}", nick, user, host);
+    ignores
+        .lock()
+        .is_ok_and(|ignores| ignores.iter().any(|mask| wildcard_match(mask, &source)))
+}
+
+/// How long to wait before rejoining a channel we were kicked from.
+const REJOIN_DELAY: Duration = Duration::from_secs(3);
+
+/// Kicks further apart than this don't count towards `rejoin_max_attempts`.
+const KICK_WINDOW: Duration = Duration::from_secs(60);
+
+const WHOIS_REPLIES: &[Response] = &[
+    Response::RPL_WHOISUSER,
+    Response::RPL_ENDOFWHOIS,
+    Response::ERR_NOSUCHNICK,
+    Response::ERR_NOSUCHSERVER,
+    Response::ERR_NONICKNAMEGIVEN,
+];
+const LIST_REPLIES: &[Response] = &[
+    Response::RPL_LISTSTART,
+    Response::RPL_LIST,
+    Response::RPL_LISTEND,
+];
+const MOTD_REPLIES: &[Response] = &[
+    Response::RPL_MOTDSTART,
+    Response::RPL_ENDOFMOTD,
+    Response::ERR_NOMOTD,
+];
+const NAMES_REPLIES: &[Response] = &[
+    Response::RPL_NAMREPLY,
+    Response::RPL_ENDOFNAMES,
+    Response::ERR_NOSUCHCHANNEL,
+];
+
+/// Starts the reply timer for `command`.
+fn expect_reply(
+    pending: &PendingReplies,
+    command: &'static str,
+    replies: &'static [Response],
+    timeout: Duration,
+) {
+    if let Ok(mut pending) = pending.lock() {
+        pending.push(PendingReply {
+            command,
+            replies,
+            deadline: Instant::now() + timeout,
+        });
+    }
+}
+
+/// Records which channel plain messages currently go to.
+fn set_active_channel(status: &SharedStatus, channel: Option<&str>) {
+    if let Ok(mut status) = status.lock() {
+        status.channel = channel.map(str::to_string);
+    }
+}
+
+/// Pins `topic` for `channel`; an empty topic unpins it.
+fn set_topic(status: &SharedStatus, channel: &str, topic: &str) {
+    if let Ok(mut status) = status.lock() {
+        if topic.is_empty() {
+            status.topics.remove(channel);
+        } else {
+            status.topics.insert(channel.to_string(), topic.to_string());
+        }
+    }
+}
+
+/// Our nick as the server knows it: what it last told us (after a NICK
+/// too), or else the one the client asked for.
+async fn own_nick(status: &SharedStatus, client: &Mutex<Client>) -> String {
+    match status.lock().ok().and_then(|s| s.nick.clone()) {
+        Some(nick) => nick,
+        None => client.lock().await.current_nickname().to_string(),
+    }
+}
+
+/// Keeps the per-channel nick lists in `status` in step with NAMES replies
+/// and other users coming and going. `own_nick` is ours, so our own JOIN
+/// starts a fresh list and our own PART drops the channel.
+fn track_members(status: &SharedStatus, message: &Message, own_nick: &str) {
+    let Ok(mut status) = status.lock() else {
+        return;
+    };
+    let nick = message.source_nickname().unwrap_or("");
+    match &message.command {
+        Command::Response(Response::RPL_NAMREPLY, args) if args.len() >= 4 => {
+            let names = status.members.entry(args[2].clone()).or_default();
+            for name in args[3].split_whitespace() {
+                names.insert(
+                    name.trim_start_matches(['~', '&', '@', '%', '+'])
+                        .to_string(),
+                );
+            }
+        }
+        Command::JOIN(channel, _, _) => {
+            let names = status.members.entry(channel.clone()).or_default();
+            if nick == own_nick {
+                names.clear();
+            }
+            names.insert(nick.to_string());
+            if nick == own_nick {
+                if let Some(Prefix::Nickname(nick, user, host)) = &message.prefix {
+                    status.hostmask = Some(format!("{}!{}@{}", nick, user, host));
+                }
+            }
+        }
+        Command::PART(channel, _) if nick == own_nick => {
+            status.members.remove(channel);
+        }
+        Command::PART(channel, _) => {
+            if let Some(names) = status.members.get_mut(channel) {
+                names.remove(nick);
+            }
+        }
+        Command::KICK(channel, kicked, _) => {
+            if let Some(names) = status.members.get_mut(channel) {
+                names.remove(kicked);
+            }
+        }
+        Command::QUIT(_) => {
+            for names in status.members.values_mut() {
+                names.remove(nick);
+            }
+        }
+        Command::NICK(new_nick) => {
+            for names in status.members.values_mut() {
+                if names.remove(nick) {
+                    names.insert(new_nick.clone());
+                }
+            }
+            if nick == own_nick {
+                status.nick = Some(new_nick.clone());
+                if let Some(Prefix::Nickname(_, user, host)) = &message.prefix {
+                    status.hostmask = Some(format!("{}!{}@{}", new_nick, user, host));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `nick (user@host)` for a message's sender, or just the nick when the
+/// server didn't send the rest.
+fn describe_source(message: &Message) -> String {
+    match &message.prefix {
+        Some(Prefix::Nickname(nick, user, host)) if !user.is_empty() && !host.is_empty() => {
+            format!("{} ({}@{})", nick, user, host)
+        }
+        _ => message.source_nickname().unwrap_or("server").to_string(),
+    }
+}
+
+/// Resolves the client and channel for a channel-operator command, telling
+/// the user why when either is missing.
+async fn op_target(
+    client_opt: &Option<Arc<Mutex<Client>>>,
+    channel: Option<String>,
+    current_channel: &Option<String>,
+    irc_tx: &Sender<app::Message>,
+) -> Result<Option<(Arc<Mutex<Client>>, String)>> {
+    let Some(client) = client_opt else {
+        irc_tx
+            .send("Not connected. Use /connect first.".into())
+            .await?;
+        return Ok(None);
+    };
+    match channel.or_else(|| current_channel.clone()) {
+        Some(channel) => Ok(Some((Arc::clone(client), channel))),
+        None => {
+            irc_tx
+                .send("Not in a channel. Use /join or name the channel.".into())
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+/// Sends `MODE <channel> <change> <arg>` for the op/voice/ban shortcuts.
+fn spawn_mode_change(
+    client: Arc<Mutex<Client>>,
+    irc_tx: &Sender<app::Message>,
+    channel: String,
+    change: &'static str,
+    arg: String,
+) {
+    let tx_clone = irc_tx.clone();
+    tokio::spawn(async move {
+        let locked = client.lock().await;
+        if let Err(e) = locked.send(Command::Raw(
+            "MODE".into(),
+            vec![channel, change.into(), arg],
+        )) {
+            let _ = tx_clone
+                .send(app::Message::error(format!(
+                    "Error sending MODE {}: {}",
+                    change, e
+                )))
+                .await;
+        }
+    });
+}
+
+/// Renders parsed modes the way they were written, e.g. `+o alice -v bob`.
+fn join_modes<T: irc::proto::mode::ModeType>(modes: &[Mode<T>]) -> String {
+    modes
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether an ERROR from the server means we've been banned, in which case
+/// reconnecting would only be refused again.
+fn is_ban_error(reason: &str) -> bool {
+    let reason = reason.to_ascii_lowercase();
+    [
+        "k-lined", "g-lined", "z-lined", "d-lined", "kline", "gline", "zline", "dline", "akill",
+        "banned",
+    ]
+    .iter()
+    .any(|marker| reason.contains(marker))
+}
+
+fn reconnect_on_error(user_config: &UserConfig) -> bool {
+    user_config
+        .irc
+        .as_ref()
+        .and_then(|c| c.reconnect_on_error)
+        .unwrap_or(true)
+}
+
+fn ping_interval(user_config: &UserConfig) -> Duration {
+    let secs = user_config.irc.as_ref().and_then(|c| c.ping_interval_secs);
+    Duration::from_secs(secs.unwrap_or(60))
+}
+
+/// How often to PING the server regardless of traffic, for networks that
+/// drop idle-looking connections. `None` when `keepalive_secs` is unset or 0.
+fn heartbeat(user_config: &UserConfig) -> Option<Duration> {
+    let secs = user_config.irc.as_ref()?.keepalive_secs?;
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// The emoji aliases to expand in incoming messages, when
+/// `expand_incoming_emojis` is on. Loaded once per connection.
+fn incoming_emojis(user_config: &UserConfig) -> Option<Arc<EmojiConfig>> {
+    let enabled = user_config.ui.as_ref()?.expand_incoming_emojis?;
+    if !enabled {
+        return None;
+    }
+    user_config.emojis.clone().map(Arc::new)
+}
+
+/// What to answer CTCP VERSION with, or `None` when `ctcp_replies` is off
+/// and requests go unanswered.
+fn ctcp_version(user_config: &UserConfig) -> Option<String> {
+    let irc = user_config.irc.as_ref();
+    if irc.and_then(|c| c.ctcp_replies) == Some(false) {
+        return None;
+    }
+    Some(
+        irc.and_then(|c| c.ctcp_version.clone())
+            .unwrap_or_else(|| format!("meow IRC client {}", env!("CARGO_PKG_VERSION"))),
+    )
+}
+
+/// Who may invite us into a channel we then join by ourselves: `None` when
+/// `autojoin_on_invite` is off, an empty list for anyone, otherwise masks.
+fn invite_autojoin(user_config: &UserConfig) -> Option<Vec<String>> {
+    let irc = user_config.irc.as_ref()?;
+    if irc.autojoin_on_invite != Some(true) {
+        return None;
+    }
+    Some(
+        irc.invite_from
+            .iter()
+            .flatten()
+            .map(|mask| ignore_mask(mask))
+            .collect(),
+    )
+}
+
+/// How often we rejoin a channel after being kicked from it: 0 when
+/// `rejoin_on_kick` is off, otherwise `rejoin_max_attempts` (default 3).
+fn kick_rejoins(user_config: &UserConfig) -> u32 {
+    let Some(irc) = user_config.irc.as_ref() else {
+        return 0;
+    };
+    if irc.rejoin_on_kick != Some(true) {
+        return 0;
+    }
+    irc.rejoin_max_attempts.unwrap_or(3)
+}
+
+/// Whether the MOTD is shown when connecting.
+fn show_motd(user_config: &UserConfig) -> bool {
+    user_config.irc.as_ref().and_then(|c| c.show_motd) != Some(false)
+}
+
+/// Whether CTCP requests are shown in the buffer rather than handled quietly.
+fn show_ctcp(user_config: &UserConfig) -> bool {
+    user_config.irc.as_ref().and_then(|c| c.show_ctcp) == Some(true)
+}
+
+/// The answer to a CTCP request (the text between the `\x01`s), or `None`
+/// for requests meow doesn't answer.
+fn ctcp_reply(request: &str, version: &str) -> Option<String> {
+    let (command, arg) = request.split_once(' ').unwrap_or((request, ""));
+    match command.to_ascii_uppercase().as_str() {
+        "VERSION" => Some(format!("VERSION {}", version)),
+        "PING" => Some(format!("PING {}", arg)),
+        "TIME" => Some(format!("TIME {}", Local::now().to_rfc2822())),
+        _ => None,
+    }
+}
+
+fn ping_timeout(user_config: &UserConfig) -> Duration {
+    let secs = user_config.irc.as_ref().and_then(|c| c.ping_timeout_secs);
+    Duration::from_secs(secs.unwrap_or(30))
+}
+
+/// How many bytes of text fit in one PRIVMSG to `target`. The server relays
+/// it as `:nick!user@host PRIVMSG target :text\r\n` in at most 512 bytes;
+/// until we've seen our own hostmask, assume the longest one it could be.
+fn privmsg_budget(status: &SharedStatus, target: &str) -> usize {
+    let prefix_len = status
+        .lock()
+        .ok()
+        .and_then(|s| match (&s.hostmask, &s.nick) {
+            (Some(hostmask), _) => Some(hostmask.len()),
+            (None, Some(nick)) => Some(nick.len() + 1 + 10 + 1 + 63),
+            _ => None,
+        })
+        .unwrap_or(30 + 1 + 10 + 1 + 63);
+    let overhead = 1 + prefix_len + " PRIVMSG ".len() + target.len() + " :".len() + 2;
+    512usize.saturating_sub(overhead).max(16)
+}
+
+/// Splits `text` into pieces of at most `max` bytes, breaking at spaces where
+/// it can and never inside a UTF-8 character.
+fn split_message(text: &str, max: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(1, char::len_utf8);
+        }
+        let cut = rest[..end].rfind(' ').filter(|&i| i > 0).unwrap_or(end);
+        chunks.push(rest[..cut].to_string());
+        rest = rest[cut..].trim_start_matches(' ');
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest.to_string());
+    }
+    chunks
+}
+
+/// When the server says `message` happened (IRCv3 `server-time`), if it
+/// tagged it. Bouncers use this when replaying what we missed.
+pub fn server_time(message: &Message) -> Option<DateTime<Local>> {
+    let tags = message.tags.as_ref()?;
+    let time = tags.iter().find(|tag| tag.0 == "time")?.1.as_deref()?;
+    DateTime::parse_from_rfc3339(time)
+        .ok()
+        .map(|t| t.with_timezone(&Local))
+}
+
+/// Sends the lines shown for one incoming message, stamped with the
+/// server's time for it when there is one instead of the arrival time.
+struct Stamped<'a> {
+    tx: &'a Sender<app::Message>,
+    time: Option<DateTime<Local>>,
+}
+
+impl Stamped<'_> {
+    async fn send(
+        &self,
+        mut line: app::Message,
+    ) -> Result<(), mpsc::error::SendError<app::Message>> {
+        if let Some(time) = self.time {
+            line.timestamp = time;
+        }
+        self.tx.send(line).await
+    }
+}
+
+/// A PRIVMSG or NOTICE waiting its turn, with what to show once it's sent.
+struct Outgoing {
+    command: Command,
+    echo: Option<app::Message>, // None for all but the last copy of a message to several targets
+    error: String,              // prefix for the error shown if sending fails
+}
+
+/// Flood protection for outgoing messages: a token bucket that allows
+/// `flood_burst` messages at once, then refills at `flood_rate` per second.
+/// Servers kill clients that send too fast, so pasting a wall of text
+/// shouldn't get us disconnected.
+struct SendQueue {
+    queue: VecDeque<Outgoing>,
+    tokens: f64,
+    rate: f64,
+    burst: f64,
+    refilled: Instant,
+}
+
+impl SendQueue {
+    fn new(user_config: &UserConfig) -> Self {
+        let mut queue = SendQueue {
+            queue: VecDeque::new(),
+            tokens: 0.0,
+            rate: 0.0,
+            burst: 0.0,
+            refilled: Instant::now(),
+        };
+        queue.configure(user_config);
+        queue.tokens = queue.burst;
+        queue
+    }
+
+    fn configure(&mut self, user_config: &UserConfig) {
+        let irc = user_config.irc.as_ref();
+        self.rate = irc
+            .and_then(|c| c.flood_rate)
+            .filter(|r| *r > 0.0)
+            .unwrap_or(0.5);
+        self.burst = irc.and_then(|c| c.flood_burst).unwrap_or(5).max(1) as f64;
+        self.tokens = self.tokens.min(self.burst);
+    }
+
+    fn push(&mut self, command: Command, echo: Option<app::Message>, error: String) {
+        self.queue.push_back(Outgoing {
+            command,
+            echo,
+            error,
+        });
+    }
+
+    /// The next message, if the rate allows sending one now.
+    fn pop_ready(&mut self) -> Option<Outgoing> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled = now;
+        if self.tokens < 1.0 {
+            return None;
+        }
+        let next = self.queue.pop_front()?;
+        self.tokens -= 1.0;
+        Some(next)
+    }
+}
+
+/// How long to wait before reconnect attempt number `attempt` (1-based):
+/// `reconnect_base_secs` more per attempt, capped at `reconnect_max_secs`,
+/// plus up to a second of jitter so many clients don't retry in lockstep.
+fn reconnect_delay(user_config: &UserConfig, attempt: u32) -> Duration {
+    let irc = user_config.irc.as_ref();
+    let base = irc.and_then(|c| c.reconnect_base_secs).unwrap_or(5);
+    let max = irc.and_then(|c| c.reconnect_max_secs).unwrap_or(60);
+    let jitter_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64 % 1000)
+        .unwrap_or(0);
+    Duration::from_secs(base.saturating_mul(attempt as u64).min(max))
+        + Duration::from_millis(jitter_ms)
+}
+
+/// Turns a bracketed IPv6 literal like `[2001:db8::1]` into the bare address
+/// the resolver (and TLS) expect. Hostnames and bare addresses pass through.
+pub(crate) fn normalize_host(server: &str) -> &str {
+    server
+        .strip_prefix('[')
+        .and_then(|s| s.strip_suffix(']'))
+        .unwrap_or(server)
+}
+
+/// Describes a failed connection, naming the proxy when it was the proxy
+/// that failed rather than the IRC server.
+fn connect_error(e: &anyhow::Error, config: &Config) -> String {
+    match e.downcast_ref::<irc::error::Error>() {
+        Some(irc::error::Error::Proxy(inner)) => format!(
+            "Error connecting through proxy {}:{}: {}",
+            config.proxy_server(),
+            config.proxy_port(),
+            inner
+        ),
+        _ => format!("Error connecting: {}", e),
+    }
+}
+
+/// Checks a certificate file named by `setting` before the irc crate gets
+/// it, since its own error for a missing or unreadable file doesn't say
+/// where the path came from. Returns the path with `~/` expanded.
+fn cert_file(setting: &str, path: &str, convert: &str) -> Result<String, String> {
+    let path = expand_home(path);
+    let data = std::fs::read(&path)
+        .map_err(|e| format!("Could not read {} {}: {}", setting, path.display(), e))?;
+    if data.starts_with(b"-----BEGIN") {
+        return Err(format!(
+            "{} {} is a PEM file; convert it with `{}`",
+            setting,
+            path.display(),
+            convert
+        ));
+    }
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Control characters a terminal would act on: ESC (and so any ANSI/OSC
+/// sequence), the other C0 and C1 controls, and DEL. mIRC formatting codes and
+/// CTCP's `\x01` aren't terminal controls and are left for later handling.
+fn is_unsafe_control(c: char) -> bool {
+    c.is_control()
+        && !matches!(
+            c,
+            '\x01' | '\x02' | '\x03' | '\x0f' | '\x16' | '\x1d' | '\x1e' | '\x1f'
+        )
+}
+
+/// Rebuilds `message` without terminal control characters, so nothing a
+/// remote user sends can move the cursor, retitle the window and so on. The
+/// only escape sequences that reach the screen are the ones meow writes.
+fn sanitize(message: Message) -> Option<Message> {
+    let raw = message.to_string();
+    let raw = raw.trim_end_matches(['\r', '\n']);
+    if !raw.chars().any(is_unsafe_control) {
+        return Some(message);
+    }
+    let clean: String = raw.chars().filter(|&c| !is_unsafe_control(c)).collect();
+    clean.parse().ok()
+}
+
+/// `name` with a `#` in front unless it already starts with a channel
+/// prefix, so `/join meow` joins `#meow` rather than getting an error.
+fn normalize_channel(name: &str) -> String {
+    if name.starts_with(['#', '&', '+', '!']) {
+        name.to_string()
+    } else {
+        format!("#{}", name)
+    }
+}
+
+/// Splits a `/join` or `/part` argument like `#a,#b c` into channel names,
+/// each normalized to have a channel prefix.
+fn split_channels(arg: &str) -> Vec<String> {
+    arg.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|c| !c.is_empty())
+        .map(normalize_channel)
+        .collect()
+}
+
+/// Clears what the status line shows about the connection once it's gone.
+fn set_offline(status: &SharedStatus) {
+    if let Ok(mut status) = status.lock() {
+        status.server = None;
+        status.away = false;
+        status.auto_away = false;
+        status.lag = None;
+        status.invite = None;
+    }
+}
+
+/// `config` with `channels` (and any keys) set to be joined once the server
+/// has finished registration. Joining any earlier gets "not registered".
+fn with_rejoin(mut config: Config, channels: &[(String, Option<String>)]) -> Config {
+    config.channels = channels.iter().map(|(c, _)| c.clone()).collect();
+    config.channel_keys = channels
+        .iter()
+        .filter_map(|(c, key)| Some((c.clone(), key.clone()?)))
+        .collect();
+    config
+}
+
+/// The next command to handle: any put off while reconnecting, then new ones.
+async fn next_command(
+    deferred: &mut VecDeque<InputCommand>,
+    input_rx: &mut Receiver<InputCommand>,
+) -> Option<InputCommand> {
+    match deferred.pop_front() {
+        Some(cmd) => Some(cmd),
+        None => input_rx.recv().await,
+    }
+}
 
 /// Runs the IRC client logic, handling connect, join, messaging, and receiving.
 /// This function now also manages auto-reconnection.
 pub async fn run_irc(
-    irc_tx: Sender<String>, // Sender for messages to be displayed in the UI
+    irc_tx: Sender<app::Message>, // Sender for messages to be displayed in the UI
     input_tx: Sender<InputCommand>, // Sender for commands to the IRC client (e.g., from UI input)
     mut input_rx: Receiver<InputCommand>, // Receiver for commands from the UI
-    accent_color_hex: Option<String>,
+    status: SharedStatus, // Session state shown by the UI (active channel, topics)
+    json_events: bool,    // Headless mode: report incoming messages as JSON lines
 ) -> Result<()> {
-    let user_config = UserConfig::load().unwrap_or_default();
-    let accent_color = accent_color_hex.and_then(|hex| parse_color(&hex));
+    let mut user_config = UserConfig::load().unwrap_or_default();
     let mut client_opt: Option<Arc<Mutex<Client>>> = None; // Stores the active IRC client
-    let mut current_channel: Option<String> = None; // Stores the currently joined channel (for rejoining)
+    let mut current_channel: Option<String> = None; // Channel plain messages go to
+    let mut joined_channels: Vec<(String, Option<String>)> = Vec::new(); // Every channel we're in (and its key), rejoined after a reconnect
+    let mut kicks: HashMap<String, (u32, Instant)> = HashMap::new(); // Kicks in a row per channel, and when the last was
     let mut last_config: Option<Config> = None; // Stores the configuration for the last successful connection
+    let pending: PendingReplies = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let pings: PingTimes = Arc::new(std::sync::Mutex::new(HashMap::new()));
+    let ignores: IgnoreList = Arc::new(std::sync::Mutex::new(
+        user_config
+            .irc
+            .as_ref()
+            .and_then(|c| c.ignore.as_ref())
+            .map(|masks| masks.iter().map(|m| ignore_mask(m)).collect())
+            .unwrap_or_default(),
+    ));
+    let mut command_timeout = Duration::from_secs(
+        user_config
+            .irc
+            .as_ref()
+            .and_then(|c| c.command_timeout_secs)
+            .unwrap_or(30),
+    );
+    let mut pending_check = tokio::time::interval(Duration::from_secs(1));
+
+    // autoconnect: act as if `/connect` had been typed with the configured defaults.
+    if let Some(irc) = user_config
+        .irc
+        .as_ref()
+        .filter(|c| c.autoconnect == Some(true))
+    {
+        if let Some(server) = irc.server.clone() {
+            let nick = irc.nick.clone().unwrap_or_else(|| "meow".to_string());
+            let tls = irc.tls.unwrap_or(true);
+            input_tx
+                .send(InputCommand::Connect {
+                    server,
+                    port: irc.port.unwrap_or(default_port(tls)),
+                    nick,
+                    tls,
+                    password: irc.server_password.clone(),
+                })
+                .await?;
+        }
+    }
+    let mut send_queue = SendQueue::new(&user_config);
+    let mut send_tick = tokio::time::interval(Duration::from_millis(100));
+    let mut listen_ctx = ListenContext {
+        irc_tx: irc_tx.clone(),
+        input_tx: input_tx.clone(),
+        pending: pending.clone(),
+        pings: pings.clone(),
+        ignores: ignores.clone(),
+        status: status.clone(),
+        reconnect_on_error: reconnect_on_error(&user_config),
+        ping_interval: ping_interval(&user_config),
+        ping_timeout: ping_timeout(&user_config),
+        heartbeat: heartbeat(&user_config),
+        json_events,
+        incoming_emojis: incoming_emojis(&user_config),
+        ctcp_version: ctcp_version(&user_config),
+        show_ctcp: show_ctcp(&user_config),
+        show_motd: show_motd(&user_config),
+        invite_autojoin: invite_autojoin(&user_config),
+    };
+
+    // Commands that arrived while reconnecting, handled before any new ones.
+    let mut deferred: VecDeque<InputCommand> = VecDeque::new();
 
     loop {
         // Use tokio::select to concurrently listen for new commands and handle them.
         select! {
-            maybe_cmd = input_rx.recv() => {
+            maybe_cmd = next_command(&mut deferred, &mut input_rx) => {
                 match maybe_cmd {
                     Some(cmd) => {
                         match cmd {
-                            InputCommand::Connect { server, port, nick, tls } => {
+                            InputCommand::Connect { server, port, nick, tls, password } => {
+                                // A new session: the server will tell us our nick again.
+                                if let Ok(mut status) = status.lock() {
+                                    status.nick = None;
+                                    status.hostmask = None;
+                                }
+
                                 // Create a new IRC client configuration.
+                                let host = normalize_host(&server).to_string();
+                                let proxy = user_config.irc.as_ref().and_then(|c| c.proxy.clone());
+                                // The irc crate switches to these itself when the server says a nick is taken.
+                                let alt_nicks = user_config
+                                    .irc
+                                    .as_ref()
+                                    .and_then(|c| c.alt_nicks.clone())
+                                    .unwrap_or_else(|| vec![format!("{}_", nick), format!("{}__", nick), format!("{}___", nick)]);
                                 let config = Config {
                                     nickname: Some(nick.clone()),
+                                    alt_nicks,
                                     username: Some(nick.clone()),
                                     realname: Some("meow IRC Client".into()),
-                                    server: Some(server.clone()),
+                                    server: Some(host.clone()),
                                     port: Some(port),
                                     use_tls: Some(tls),
+                                    password,
+                                    proxy_type: proxy.as_ref().map(|_| ProxyType::Socks5),
+                                    proxy_server: proxy.as_ref().map(|p| p.host.clone()),
+                                    proxy_port: proxy.as_ref().and_then(|p| p.port),
+                                    proxy_username: proxy.as_ref().and_then(|p| p.username.clone()),
+                                    proxy_password: proxy.as_ref().and_then(|p| p.password.clone()),
                                     ..Default::default()
                                 };
 
+                                // Connecting to the configured server joins `autojoin` once registered.
+                                let autojoin: Vec<(String, Option<String>)> = user_config
+                                    .irc
+                                    .as_ref()
+                                    .filter(|c| c.server.as_deref().map(normalize_host) == Some(host.as_str()))
+                                    .and_then(|c| c.autojoin.clone())
+                                    .unwrap_or_default()
+                                    .into_iter()
+                                    .map(|entry| match entry.split_once(' ') {
+                                        // "#channel key" for channels that need one.
+                                        Some((channel, key)) => (normalize_channel(channel), Some(key.trim().to_string())),
+                                        None => (normalize_channel(&entry), None),
+                                    })
+                                    .collect();
+                                let config = if autojoin.is_empty() { config } else { with_rejoin(config, &autojoin) };
+
+                                // Certificates only matter over TLS; check them here so a bad path
+                                // gets a clear message instead of a generic connection failure.
+                                let irc_config = user_config.irc.as_ref().filter(|_| tls);
+                                let ca_cert = irc_config.and_then(|c| c.tls_ca_cert.as_deref()).map(|path| {
+                                    cert_file("tls_ca_cert", path, "openssl x509 -in ca.pem -outform der -out ca.der")
+                                });
+                                let client_cert = irc_config.and_then(|c| c.tls_client_cert.as_deref()).map(|path| {
+                                    cert_file("tls_client_cert", path, "openssl pkcs12 -export -in cert.pem -inkey key.pem -out meow.p12")
+                                });
+                                let (cert_path, client_cert_path) = match (ca_cert.transpose(), client_cert.transpose()) {
+                                    (Ok(ca), Ok(client)) => (ca, client),
+                                    (Err(e), _) | (_, Err(e)) => {
+                                        irc_tx.send(app::Message::error(e)).await?;
+                                        continue;
+                                    }
+                                };
+                                let config = Config {
+                                    cert_path,
+                                    client_cert_path,
+                                    client_cert_pass: irc_config.and_then(|c| c.tls_client_cert_pass.clone()),
+                                    dangerously_accept_invalid_certs: irc_config.and_then(|c| c.accept_invalid_certs),
+                                    ..config
+                                };
+                                // Opt-in only, and never quietly.
+                                if config.dangerously_accept_invalid_certs == Some(true) {
+                                    irc_tx.send(app::Message::error("*** WARNING: TLS certificate not verified")).await?;
+                                }
+
+                                // Resolve first (A and AAAA) so a bad hostname gets a clear message.
+                                // Behind a proxy the proxy resolves it, so nothing leaks locally.
+                                if proxy.is_none() {
+                                    if let Err(e) = tokio::net::lookup_host((host.as_str(), port)).await {
+                                        irc_tx.send(app::Message::error(format!("Could not resolve {}: {}", server, e))).await?;
+                                        continue;
+                                    }
+                                }
+
+                                // Leave the server we're on first. Letting go of the client tells its
+                                // listener not to reconnect; wait (briefly) for it to see the QUIT through.
+                                let mut new_session = last_config.as_ref().and_then(|c| c.server.as_deref()) != Some(host.as_str());
+                                if let Some(old) = client_opt.take() {
+                                    irc_tx.send("*** Disconnecting from old server".into()).await?;
+                                    let message = user_config
+                                        .irc
+                                        .as_ref()
+                                        .and_then(|c| c.quit_message.clone())
+                                        .unwrap_or_else(|| "Bye!".to_string());
+                                    let _ = old.lock().await.send_quit(message);
+                                    let listener = Arc::downgrade(&old);
+                                    drop(old);
+                                    let deadline = Instant::now() + Duration::from_secs(5);
+                                    while listener.strong_count() > 0 && Instant::now() < deadline {
+                                        sleep(Duration::from_millis(50)).await;
+                                    }
+                                    set_offline(&status);
+                                    new_session = true;
+                                }
+                                // Channels kept for /reconnect after a dropped connection don't carry
+                                // over to another server.
+                                if new_session {
+                                    current_channel = None;
+                                    joined_channels.clear();
+                                    send_queue.queue.clear();
+                                    set_active_channel(&status, None);
+                                }
+
                                 // Attempt to connect and start listening using the helper function.
-                                match connect_and_listen(config.clone(), irc_tx.clone(), input_tx.clone(), accent_color.clone()).await {
+                                // The handshake can take a while: /connect, /disconnect and /quit give
+                                // up on it, anything else waits until it's over.
+                                irc_tx.send(format!("*** Connecting to {}:{}...", server, port).into()).await?;
+                                if let Ok(mut status) = status.lock() {
+                                    status.connecting = Some((host.clone(), std::time::Instant::now()));
+                                }
+                                let attempt = connect_and_listen(config.clone(), listen_ctx.clone(), false);
+                                tokio::pin!(attempt);
+                                let result = loop {
+                                    select! {
+                                        result = &mut attempt => break Some(result),
+                                        maybe_cmd = input_rx.recv() => match maybe_cmd {
+                                            Some(cmd) => {
+                                                let stop = matches!(cmd, InputCommand::Connect { .. } | InputCommand::Disconnect(_) | InputCommand::Quit(_));
+                                                deferred.push_back(cmd);
+                                                if stop {
+                                                    break None;
+                                                }
+                                            }
+                                            None => break None,
+                                        },
+                                    }
+                                };
+                                if let Ok(mut status) = status.lock() {
+                                    status.connecting = None;
+                                }
+                                let Some(result) = result else {
+                                    irc_tx.send(format!("*** Stopped connecting to {}", server).into()).await?;
+                                    continue;
+                                };
+                                match result {
                                     Ok(client) => {
                                         // On successful connection, update client_opt and store the config.
                                         irc_tx.send(format!(
@@ -54,42 +880,165 @@ pub async fn run_irc(
                                             port,
                                             nick,
                                             if tls { "with" } else { "without" }
-                                        )).await?;
+                                        ).into()).await?;
                                         client_opt = Some(client);
                                         last_config = Some(config); // Store this config for potential reconnects
+                                        if let Some((channel, _)) = autojoin.last() {
+                                            set_active_channel(&status, Some(channel));
+                                            current_channel = Some(channel.clone());
+                                            joined_channels = autojoin;
+                                        }
                                     }
                                     Err(e) => {
                                         // Report connection errors to the UI.
-                                        let _ = irc_tx.send(format!("Error connecting: {}", e)).await;
+                                        let _ = irc_tx.send(app::Message::error(connect_error(&e, &config))).await;
                                     }
                                 }
                             }
 
                             InputCommand::SendMessage { target, message } => {
-                                // If connected, send the message.
+                                // If connected, queue the message; it's shown once it goes out.
+                                // `a,b,c` sends a copy to each, echoed once.
+                                let targets: Vec<&str> = target.split(',').collect();
+                                if targets.iter().any(|t| t.is_empty()) {
+                                    irc_tx.send(app::Message::error(format!("Empty target in {}", target))).await?;
+                                } else if let Some(client) = &client_opt {
+                                    let processed_message = match &user_config.emojis {
+                                        Some(emojis_config) => emojis_config.expand(&message),
+                                        None => message.clone(),
+                                    };
+
+                                    let nick = own_nick(&status, client).await;
+                                    // Too long for one line: send it in pieces, each shown as it goes out.
+                                    // Pieces are sized for the longest target so every copy fits.
+                                    let longest = targets.iter().max_by_key(|t| t.len()).copied().unwrap_or_default();
+                                    for chunk in split_message(&processed_message, privmsg_budget(&status, longest)) {
+                                        let echo = app::Message::new(MessageKind::Privmsg, chunk.clone()).with_sender(&nick, &target).outgoing();
+                                        for (i, to) in targets.iter().enumerate() {
+                                            let echo = (i + 1 == targets.len()).then(|| echo.clone());
+                                            let error = format!("Error sending to {}", to);
+                                            send_queue.push(Command::PRIVMSG(to.to_string(), chunk.clone()), echo, error);
+                                        }
+                                    }
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::SendNotice { target, message } => {
+                                if let Some(client) = &client_opt {
+                                    let nick = own_nick(&status, client).await;
+                                    let echo = app::Message::new(MessageKind::Notice, message.clone()).with_sender(&nick, &target).outgoing();
+                                    let error = format!("Error sending notice to {}", target);
+                                    send_queue.push(Command::NOTICE(target, message), Some(echo), error);
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::JoinChannel(arg, keys) => {
+                                // If connected, join each of the given channels; the last one becomes current.
+                                // A bare /join accepts the last invite.
+                                let arg = match status.lock().ok().and_then(|s| s.invite.clone()) {
+                                    Some(invite) if arg.trim().is_empty() => invite,
+                                    _ => arg,
+                                };
+                                if let Some(client) = &client_opt {
+                                    // Keys pair up with the channels in order, like JOIN itself.
+                                    let keys: Vec<&str> = keys.as_deref().unwrap_or("").split(',').collect();
+                                    for (i, channel) in split_channels(&arg).into_iter().enumerate() {
+                                        let key = keys.get(i).filter(|k| !k.is_empty()).map(|k| k.to_string());
+                                        let client = Arc::clone(client);
+                                        let tx_clone = irc_tx.clone();
+                                        let channel_clone = channel.clone();
+                                        let key_clone = key.clone();
+
+                                        tokio::spawn(async move {
+                                            let locked = client.lock().await;
+                                            let sent = match &key_clone {
+                                                Some(key) => locked.send_join_with_keys::<&str, &str>(&channel_clone, key),
+                                                None => locked.send_join(&channel_clone),
+                                            };
+                                            if let Err(e) = sent {
+                                                let _ = tx_clone.send(app::Message::error(format!("Error joining {}: {}", channel_clone, e))).await;
+                                            } else {
+                                                let _ = tx_clone.send(format!("*** Joined {}", channel_clone).into()).await;
+                                            }
+                                        });
+
+                                        // Remember the key so a reconnect can rejoin.
+                                        match joined_channels.iter_mut().find(|(c, _)| c == &channel) {
+                                            Some(joined) => {
+                                                if key.is_some() {
+                                                    joined.1 = key;
+                                                }
+                                            }
+                                            None => joined_channels.push((channel.clone(), key)),
+                                        }
+                                        set_active_channel(&status, Some(&channel));
+                                        current_channel = Some(channel); // Update the current channel
+                                    }
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::OpenQuery(nick) => {
+                                // Plain messages go to this nick until another channel or query is opened.
+                                if client_opt.is_some() {
+                                    set_active_channel(&status, Some(&nick));
+                                    irc_tx.send(format!("*** Talking privately with {}", nick).into()).await?;
+                                    current_channel = Some(nick);
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::SetAway(message) => {
+                                // The server confirms with 306/305, which the listener reports.
                                 if let Some(client) = &client_opt {
                                     let client = Arc::clone(client);
                                     let tx_clone = irc_tx.clone();
-                                    let target_clone = target.clone();
-                                    let mut processed_message = message.clone();
 
-                                    if let Some(emojis_config) = &user_config.emojis {
-                                        for (alias, emoji) in &emojis_config.aliases {
-                                            processed_message = processed_message.replace(&format!(":{}:", alias), emoji);
+                                    tokio::spawn(async move {
+                                        let locked = client.lock().await;
+                                        if let Err(e) = locked.send(Command::AWAY(message)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error setting away status: {}", e))).await;
+                                        }
+                                    });
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::AutoAway(idle) => {
+                                // The listener reports it once the server confirms.
+                                if let Some(client) = &client_opt {
+                                    if let Ok(mut status) = status.lock() {
+                                        if idle {
+                                            status.auto_away = true;
+                                        } else if !status.auto_away {
+                                            continue; // set by hand meanwhile; leave it
                                         }
                                     }
+                                    let message = idle.then(|| "idle".to_string());
+                                    if let Err(e) = client.lock().await.send(Command::AWAY(message)) {
+                                        irc_tx.send(app::Message::error(format!("Error setting away status: {}", e))).await?;
+                                    }
+                                }
+                            }
+
+                            InputCommand::Mode { target, args } => {
+                                if let Some(client) = &client_opt {
+                                    let client = Arc::clone(client);
+                                    let tx_clone = irc_tx.clone();
+                                    let mut params = vec![target];
+                                    params.extend(args);
 
                                     tokio::spawn(async move {
                                         let locked = client.lock().await;
-                                        if let Err(e) = locked.send_privmsg(&target_clone, &processed_message) {
-                                            let _ = tx_clone.send(format!("Error sending to {}: {}", target_clone, e)).await;
-                                        } else {
-                                            let color_code = if let Some(crossterm::style::Color::Rgb { r, g, b }) = accent_color {
-                                                format!("38;2;{};{};{}", r, g, b)
-                                            } else {
-                                                "38;2;128;0;128".to_string() // Default purple
-                                            };
-                                            let _ = tx_clone.send(format!("\x1b[1m\x1b[{}m<You->{}>\x1b[0m {}", color_code, target_clone, processed_message)).await;
+                                        if let Err(e) = locked.send(Command::Raw("MODE".into(), params)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error sending MODE: {}", e))).await;
                                         }
                                     });
                                 } else {
@@ -97,137 +1046,426 @@ pub async fn run_irc(
                                 }
                             }
 
-                            InputCommand::JoinChannel(channel) => {
-                                // If connected, join the specified channel.
+                            InputCommand::Kick { channel, nick, reason } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    let tx_clone = irc_tx.clone();
+
+                                    tokio::spawn(async move {
+                                        let locked = client.lock().await;
+                                        if let Err(e) = locked.send(Command::KICK(channel, nick.clone(), reason)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error kicking {}: {}", nick, e))).await;
+                                        }
+                                    });
+                                }
+                            }
+
+                            InputCommand::Ban { channel, mask } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    spawn_mode_change(client, &irc_tx, channel, "+b", mask);
+                                }
+                            }
+
+                            InputCommand::Op { channel, nick } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    spawn_mode_change(client, &irc_tx, channel, "+o", nick);
+                                }
+                            }
+
+                            InputCommand::Deop { channel, nick } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    spawn_mode_change(client, &irc_tx, channel, "-o", nick);
+                                }
+                            }
+
+                            InputCommand::Voice { channel, nick } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    spawn_mode_change(client, &irc_tx, channel, "+v", nick);
+                                }
+                            }
+
+                            InputCommand::Devoice { channel, nick } => {
+                                if let Some((client, channel)) = op_target(&client_opt, channel, &current_channel, &irc_tx).await? {
+                                    spawn_mode_change(client, &irc_tx, channel, "-v", nick);
+                                }
+                            }
+
+                            InputCommand::Whois(nick) => {
                                 if let Some(client) = &client_opt {
                                     let client = Arc::clone(client);
                                     let tx_clone = irc_tx.clone();
-                                    let channel_clone = channel.clone();
+                                    expect_reply(&pending, "/whois", WHOIS_REPLIES, command_timeout);
 
                                     tokio::spawn(async move {
                                         let locked = client.lock().await;
-                                        if let Err(e) = locked.send_join(&channel_clone) {
-                                            let _ = tx_clone.send(format!("Error joining {}: {}", channel_clone, e)).await;
-                                        } else {
-                                            let _ = tx_clone.send(format!("*** Joined {}", channel_clone)).await;
+                                        if let Err(e) = locked.send(Command::WHOIS(None, nick.clone())) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error sending WHOIS for {}: {}", nick, e))).await;
                                         }
                                     });
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::Ping => {
+                                if let Some(client) = &client_opt {
+                                    let client = Arc::clone(client);
+                                    let tx_clone = irc_tx.clone();
+                                    let sent = Instant::now();
+                                    let stamp = std::time::SystemTime::now()
+                                        .duration_since(std::time::UNIX_EPOCH)
+                                        .map(|d| d.as_nanos())
+                                        .unwrap_or_default();
+                                    let token = format!("meow-lag-{}", stamp);
+                                    if let Ok(mut pings) = pings.lock() {
+                                        pings.insert(token.clone(), sent);
+                                    }
 
-                                    current_channel = Some(channel); // Update the current channel
+                                    tokio::spawn(async move {
+                                        let locked = client.lock().await;
+                                        if let Err(e) = locked.send(Command::PING(token, None)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error sending PING: {}", e))).await;
+                                        }
+                                    });
                                 } else {
                                     irc_tx.send("Not connected. Use /connect first.".into()).await?;
                                 }
                             }
 
-                            InputCommand::PartChannel(channel) => {
-                                // If connected, part the specified channel.
+                            InputCommand::Motd => {
                                 if let Some(client) = &client_opt {
                                     let client = Arc::clone(client);
                                     let tx_clone = irc_tx.clone();
-                                    let channel_clone = channel.clone();
+                                    expect_reply(&pending, "/motd", MOTD_REPLIES, command_timeout);
 
                                     tokio::spawn(async move {
                                         let locked = client.lock().await;
-                                        if let Err(e) = locked.send_part(&channel_clone) {
-                                            let _ = tx_clone.send(format!("Error parting {}: {}", channel_clone, e)).await;
-                                        } else {
-                                            let _ = tx_clone.send(format!("*** Left {}", channel_clone)).await;
+                                        if let Err(e) = locked.send(Command::MOTD(None)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error sending MOTD: {}", e))).await;
                                         }
                                     });
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
 
-                                    // If the parted channel was the current one, clear it.
-                                    if current_channel.as_ref() == Some(&channel) {
-                                        current_channel = None;
+                            InputCommand::Ignore(None) => {
+                                let list = ignores.lock().map(|i| i.join(", ")).unwrap_or_default();
+                                let report = if list.is_empty() {
+                                    "*** Not ignoring anyone".to_string()
+                                } else {
+                                    format!("*** Ignoring {}", list)
+                                };
+                                irc_tx.send(report.into()).await?;
+                            }
+
+                            InputCommand::Ignore(Some(mask)) => {
+                                let mask = ignore_mask(&mask);
+                                if let Ok(mut ignores) = ignores.lock() {
+                                    if !ignores.iter().any(|m| m.eq_ignore_ascii_case(&mask)) {
+                                        ignores.push(mask.clone());
+                                    }
+                                }
+                                irc_tx.send(format!("*** Ignoring {}", mask).into()).await?;
+                            }
+
+                            InputCommand::Unignore(mask) => {
+                                let mask = ignore_mask(&mask);
+                                let removed = ignores.lock().is_ok_and(|mut ignores| {
+                                    let before = ignores.len();
+                                    ignores.retain(|m| !m.eq_ignore_ascii_case(&mask));
+                                    ignores.len() < before
+                                });
+                                let report = if removed {
+                                    format!("*** No longer ignoring {}", mask)
+                                } else {
+                                    format!("*** {} wasn't being ignored", mask)
+                                };
+                                irc_tx.send(report.into()).await?;
+                            }
+
+                            InputCommand::Raw(line) => {
+                                if let Some(client) = &client_opt {
+                                    match line.parse::<Message>() {
+                                        Ok(message) => {
+                                            let client = Arc::clone(client);
+                                            let tx_clone = irc_tx.clone();
+
+                                            tokio::spawn(async move {
+                                                let locked = client.lock().await;
+                                                if let Err(e) = locked.send(message) {
+                                                    let _ = tx_clone.send(app::Message::error(format!("Error sending raw line: {}", e))).await;
+                                                } else {
+                                                    let _ = tx_clone.send(format!(">> {}", line).into()).await;
+                                                }
+                                            });
+                                        }
+                                        Err(e) => {
+                                            irc_tx.send(app::Message::error(format!("Error parsing raw line: {}", e))).await?;
+                                        }
                                     }
                                 } else {
                                     irc_tx.send("Not connected. Use /connect first.".into()).await?;
                                 }
                             }
 
-                            InputCommand::Quit => {
-                                // If connected, send a quit message and then exit the loop.
+                            InputCommand::ListChannels(pattern) => {
                                 if let Some(client) = &client_opt {
-                                    let locked = client.lock().await;
-                                    let _ = locked.send_quit("Bye!");
+                                    let client = Arc::clone(client);
+                                    let tx_clone = irc_tx.clone();
+                                    expect_reply(&pending, "/list", LIST_REPLIES, command_timeout);
+
+                                    tokio::spawn(async move {
+                                        let locked = client.lock().await;
+                                        if let Err(e) = locked.send(Command::LIST(pattern, None)) {
+                                            let _ = tx_clone.send(app::Message::error(format!("Error sending LIST: {}", e))).await;
+                                        }
+                                    });
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
                                 }
-                                break; // Exit the main loop, terminating the client
                             }
 
-                            InputCommand::SendPlainMessage(message) => {
-                                // If in a channel, send a plain message to it.
-                                if let Some(channel) = &current_channel {
-                                    if let Some(client) = &client_opt {
+                            InputCommand::Names(channel) => {
+                                // Default to the current channel when none is given.
+                                match (&client_opt, channel.or_else(|| current_channel.clone())) {
+                                    (Some(client), Some(channel)) => {
                                         let client = Arc::clone(client);
                                         let tx_clone = irc_tx.clone();
-                                        let channel_clone = channel.clone();
-                                        let mut processed_message = message.clone();
+                                        expect_reply(&pending, "/names", NAMES_REPLIES, command_timeout);
 
-                                        if let Some(emojis_config) = &user_config.emojis {
-                                            for (alias, emoji) in &emojis_config.aliases {
-                                                processed_message = processed_message.replace(&format!(":{}:", alias), emoji);
+                                        tokio::spawn(async move {
+                                            let locked = client.lock().await;
+                                            if let Err(e) = locked.send(Command::NAMES(Some(channel.clone()), None)) {
+                                                let _ = tx_clone.send(app::Message::error(format!("Error sending NAMES for {}: {}", channel, e))).await;
                                             }
-                                        }
+                                        });
+                                    }
+                                    (Some(_), None) => {
+                                        irc_tx.send("Not in a channel. Use /names <#channel>.".into()).await?;
+                                    }
+                                    (None, _) => {
+                                        irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                    }
+                                }
+                            }
+
+                            InputCommand::PartChannel(arg) => {
+                                // If connected, part each of the given channels.
+                                if let Some(client) = &client_opt {
+                                    for channel in split_channels(&arg) {
+                                        let client = Arc::clone(client);
+                                        let tx_clone = irc_tx.clone();
+                                        let channel_clone = channel.clone();
 
                                         tokio::spawn(async move {
                                             let locked = client.lock().await;
-                                            if let Err(e) = locked.send_privmsg(&channel_clone, &processed_message) {
-                                                let _ = tx_clone.send(format!("Error sending: {}", e)).await;
+                                            if let Err(e) = locked.send_part(&channel_clone) {
+                                                let _ = tx_clone.send(app::Message::error(format!("Error parting {}: {}", channel_clone, e))).await;
                                             } else {
-                                                let color_code = if let Some(crossterm::style::Color::Rgb { r, g, b }) = accent_color {
-                                                    format!("38;2;{};{};{}", r, g, b)
-                                                } else {
-                                                    "38;2;128;0;128".to_string() // Default purple
-                                                };
-                                                let _ = tx_clone.send(format!("\x1b[1m\x1b[{}m<You ({}) :>\x1b[0m {}", color_code, channel_clone, processed_message)).await;
+                                                let _ = tx_clone.send(format!("*** Left {}", channel_clone).into()).await;
                                             }
                                         });
+
+                                        joined_channels.retain(|(c, _)| c != &channel);
+                                        // If the parted channel was the current one, clear it.
+                                        if current_channel.as_ref() == Some(&channel) {
+                                            current_channel = None;
+                                            set_active_channel(&status, None);
+                                        }
+                                    }
+                                } else {
+                                    irc_tx.send("Not connected. Use /connect first.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::Disconnect(message) => {
+                                // Unlike a dropped connection, this never auto-reconnects: the
+                                // listener sees the client was let go and stays quiet.
+                                if let Some(client) = client_opt.take() {
+                                    let message = message
+                                        .or_else(|| user_config.irc.as_ref()?.quit_message.clone())
+                                        .unwrap_or_else(|| "Bye!".to_string());
+                                    if let Err(e) = client.lock().await.send_quit(message) {
+                                        irc_tx.send(app::Message::error(format!("Error disconnecting: {}", e))).await?;
+                                    }
+                                    current_channel = None;
+                                    joined_channels.clear();
+                                    send_queue.queue.clear(); // don't send leftovers to the next server
+                                    set_offline(&status);
+                                    set_active_channel(&status, None);
+                                    irc_tx.send("*** Disconnected. Use /connect or /reconnect to go back online.".into()).await?;
+                                } else {
+                                    irc_tx.send("Not connected.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::Quit(message) => {
+                                // If connected, send a quit message and then exit the loop.
+                                // A message given with /quit wins over the configured one.
+                                if let Some(client) = &client_opt {
+                                    let message = message
+                                        .or_else(|| user_config.irc.as_ref()?.quit_message.clone())
+                                        .unwrap_or_else(|| "Bye!".to_string());
+                                    let locked = client.lock().await;
+                                    let _ = locked.send_quit(message);
+                                }
+                                break; // Exit the main loop, terminating the client
+                            }
+
+                            InputCommand::SendPlainMessage(message) => {
+                                // If in a channel, send a plain message to it.
+                                if let Some(channel) = &current_channel {
+                                    if let Some(client) = &client_opt {
+                                        let processed_message = match &user_config.emojis {
+                                            Some(emojis_config) => emojis_config.expand(&message),
+                                            None => message.clone(),
+                                        };
+
+                                        let nick = own_nick(&status, client).await;
+                                        for chunk in split_message(&processed_message, privmsg_budget(&status, channel)) {
+                                            let echo = app::Message::new(MessageKind::Privmsg, chunk.clone()).with_sender(&nick, channel).outgoing();
+                                            send_queue.push(Command::PRIVMSG(channel.clone(), chunk), Some(echo), "Error sending".to_string());
+                                        }
                                     }
                                 } else {
                                     irc_tx.send("Not in a channel. Use /join.".into()).await?;
                                 }
                             }
 
-                            InputCommand::Disconnected => {
+                            InputCommand::ReloadConfig(new_config) => {
+                                // Pick up new emoji aliases, colors, and IRC defaults.
+                                user_config = *new_config;
+                                send_queue.configure(&user_config);
+                                listen_ctx.reconnect_on_error = reconnect_on_error(&user_config);
+                                listen_ctx.ping_interval = ping_interval(&user_config);
+                                listen_ctx.ping_timeout = ping_timeout(&user_config);
+                                listen_ctx.heartbeat = heartbeat(&user_config);
+                                listen_ctx.incoming_emojis = incoming_emojis(&user_config);
+                                listen_ctx.ctcp_version = ctcp_version(&user_config);
+                                listen_ctx.show_ctcp = show_ctcp(&user_config);
+                                listen_ctx.show_motd = show_motd(&user_config);
+                                listen_ctx.invite_autojoin = invite_autojoin(&user_config);
+                                command_timeout = Duration::from_secs(
+                                    user_config
+                                        .irc
+                                        .as_ref()
+                                        .and_then(|c| c.command_timeout_secs)
+                                        .unwrap_or(30),
+                                );
+                            }
+
+                            InputCommand::Reconnect => {
+                                // Manual recovery, e.g. after a ban-like ERROR stopped auto-reconnect.
+                                if client_opt.is_some() {
+                                    irc_tx.send("Already connected. Use /disconnect to leave.".into()).await?;
+                                } else if let Some(config) = last_config.clone() {
+                                    irc_tx.send("*** Reconnecting...".into()).await?;
+                                    match connect_and_listen(with_rejoin(config, &joined_channels), listen_ctx.clone(), true).await {
+                                        Ok(new_client) => {
+                                            irc_tx.send("*** Reconnected successfully!".into()).await?;
+                                            client_opt = Some(new_client);
+                                        }
+                                        Err(e) => {
+                                            irc_tx.send(app::Message::error(format!("Error reconnecting: {}", e))).await?;
+                                        }
+                                    }
+                                } else {
+                                    irc_tx.send("Cannot reconnect: No previous connection configuration found.".into()).await?;
+                                }
+                            }
+
+                            InputCommand::Kicked(channel) => {
+                                let max_rejoins = kick_rejoins(&user_config);
+                                let now = Instant::now();
+                                let (count, last) = kicks.entry(channel.clone()).or_insert((0, now));
+                                if now.duration_since(*last) > KICK_WINDOW {
+                                    *count = 0;
+                                }
+                                *count += 1;
+                                *last = now;
+                                if *count <= max_rejoins {
+                                    irc_tx.send(format!("*** Rejoining {} in {}s", channel, REJOIN_DELAY.as_secs()).into()).await?;
+                                    let key = joined_channels.iter().find(|(c, _)| c == &channel).and_then(|(_, key)| key.clone());
+                                    let input_tx = input_tx.clone();
+                                    tokio::spawn(async move {
+                                        sleep(REJOIN_DELAY).await;
+                                        let _ = input_tx.send(InputCommand::JoinChannel(channel, key)).await;
+                                    });
+                                } else {
+                                    // Stay out, and don't come back on the next reconnect either.
+                                    if max_rejoins > 0 {
+                                        irc_tx.send(format!("*** Kicked from {} {} times in a row, not rejoining", channel, count).into()).await?;
+                                    }
+                                    kicks.remove(&channel);
+                                    joined_channels.retain(|(c, _)| c != &channel);
+                                    if current_channel.as_ref() == Some(&channel) {
+                                        current_channel = None;
+                                        set_active_channel(&status, None);
+                                    }
+                                }
+                            }
+
+                            InputCommand::Disconnected { reconnect: false } => {
+                                client_opt = None;
+                                set_offline(&status);
+                                irc_tx.send("*** Disconnected from IRC server. Use /reconnect to try again.".into()).await?;
+                            }
+
+                            InputCommand::Disconnected { .. } => {
                                 // Handle the disconnect signal from the message processing task.
                                 irc_tx.send("*** Disconnected from IRC server. Attempting to reconnect...".into()).await?;
                                 client_opt = None; // Invalidate the current client
+                                set_offline(&status);
 
-                                if let Some(config_to_reconnect) = last_config.clone() {
+                                if let Some(mut config_to_reconnect) = last_config.clone() {
+                                    // Come back as whatever nick we ended up with.
+                                    if let Some(nick) = status.lock().ok().and_then(|s| s.nick.clone()) {
+                                        config_to_reconnect.nickname = Some(nick);
+                                    }
+                                    // Re-join every channel we were in once the server lets us.
+                                    let config_to_reconnect = with_rejoin(config_to_reconnect, &joined_channels);
+                                    let max_attempts = user_config.irc.as_ref().and_then(|c| c.reconnect_max_attempts);
                                     let mut reconnect_attempts = 0;
                                     loop {
+                                        if max_attempts.is_some_and(|max| reconnect_attempts >= max) {
+                                            irc_tx.send(format!("*** Giving up after {} attempts. Use /reconnect to try again.", reconnect_attempts).into()).await?;
+                                            break;
+                                        }
                                         reconnect_attempts += 1;
-                                        irc_tx.send(format!("Attempting reconnection #{}...", reconnect_attempts)).await?;
-                                        // Implement exponential backoff with a maximum delay.
-                                        let delay_secs = (5 * reconnect_attempts).min(60); // Cap delay at 60 seconds
-                                        sleep(Duration::from_secs(delay_secs as u64)).await;
+                                        irc_tx.send(format!("Attempting reconnection #{}...", reconnect_attempts).into()).await?;
+                                        // Back off a little more on each attempt, up to the configured maximum.
+                                        // Meanwhile /connect, /disconnect and /quit stop the retries; anything
+                                        // else waits until this is over.
+                                        let backoff = sleep(reconnect_delay(&user_config, reconnect_attempts));
+                                        tokio::pin!(backoff);
+                                        let mut stopped = false;
+                                        while !stopped {
+                                            select! {
+                                                _ = &mut backoff => break,
+                                                maybe_cmd = input_rx.recv() => match maybe_cmd {
+                                                    Some(cmd) => {
+                                                        stopped = matches!(cmd, InputCommand::Connect { .. } | InputCommand::Disconnect(_) | InputCommand::Quit(_));
+                                                        deferred.push_back(cmd);
+                                                    }
+                                                    None => stopped = true,
+                                                },
+                                            }
+                                        }
+                                        if stopped {
+                                            irc_tx.send("*** Stopped reconnecting".into()).await?;
+                                            break;
+                                        }
 
                                         // Attempt to reconnect using the stored configuration.
-                                        match connect_and_listen(config_to_reconnect.clone(), irc_tx.clone(), input_tx.clone(), accent_color.clone()).await {
+                                        match connect_and_listen(config_to_reconnect.clone(), listen_ctx.clone(), true).await {
                                             Ok(new_client) => {
-                                                irc_tx.send(format!("*** Reconnected successfully!")).await?;
+                                                irc_tx.send("*** Reconnected successfully!".into()).await?;
                                                 client_opt = Some(new_client); // Set the new client
-
-                                                // If a channel was previously joined, attempt to re-join it.
-                                                if let Some(channel) = &current_channel {
-                                                    if let Some(client_ref) = client_opt.as_ref() {
-                                                        let client_rejoin = Arc::clone(client_ref);
-                                                        let tx_rejoin = irc_tx.clone();
-                                                        let channel_rejoin = channel.clone();
-                                                        tokio::spawn(async move {
-                                                            let locked = client_rejoin.lock().await;
-                                                            if let Err(e) = locked.send_join(&channel_rejoin) {
-                                                                let _ = tx_rejoin.send(format!("Error rejoining {}: {}", channel_rejoin, e)).await;
-                                                            } else {
-                                                                let _ = tx_rejoin.send(format!("*** Rejoined {}", channel_rejoin)).await;
-                                                            }
-                                                        });
-                                                    }
-                                                }
                                                 break; // Break out of the reconnection loop
                                             }
                                             Err(e) => {
                                                 // Report reconnection attempt failures.
-                                                irc_tx.send(format!("Error during reconnection attempt #{}: {}", reconnect_attempts, e)).await?;
+                                                irc_tx.send(app::Message::error(format!("Error during reconnection attempt #{}: {}", reconnect_attempts, e))).await?;
                                                 // Continue to the next attempt after the delay.
                                             }
                                         }
@@ -242,24 +1480,118 @@ pub async fn run_irc(
                     None => break, // Input channel closed; exit the main loop.
                 }
             }
+
+            _ = send_tick.tick() => {
+                // Send whatever flood protection allows, echoing each line as it goes out.
+                if let Some(client) = &client_opt {
+                    while let Some(out) = send_queue.pop_ready() {
+                        let result = client.lock().await.send(out.command);
+                        match result {
+                            Ok(()) => {
+                                if let Some(echo) = out.echo {
+                                    irc_tx.send(echo).await?;
+                                }
+                            }
+                            Err(e) => irc_tx.send(app::Message::error(format!("{}: {}", out.error, e))).await?,
+                        }
+                    }
+                }
+            }
+
+            _ = pending_check.tick() => {
+                // Report requests the server never answered.
+                let expired: Vec<&'static str> = match pending.lock() {
+                    Ok(mut pending) => {
+                        let now = Instant::now();
+                        let expired = pending.iter().filter(|p| p.deadline <= now).map(|p| p.command).collect();
+                        pending.retain(|p| p.deadline > now);
+                        expired
+                    }
+                    Err(_) => Vec::new(),
+                };
+                for command in expired {
+                    irc_tx.send(format!("No response from server for {}", command).into()).await?;
+                }
+            }
         }
     }
 
     Ok(())
 }
 
-async fn connect_and_listen(
-    config: Config,
-    irc_tx: Sender<String>,
+/// Everything the listener task needs besides the connection itself.
+#[derive(Clone)]
+struct ListenContext {
+    irc_tx: Sender<app::Message>,
     input_tx: Sender<InputCommand>,
-    accent_color: Option<crossterm::style::Color>,
+    pending: PendingReplies,
+    pings: PingTimes,
+    ignores: IgnoreList,
+    status: SharedStatus,
+    reconnect_on_error: bool,
+    ping_interval: Duration, // send our own PING after this long without traffic
+    ping_timeout: Duration,  // then wait this long for anything before giving up
+    heartbeat: Option<Duration>, // `keepalive_secs`: PING on this interval no matter what
+    json_events: bool,       // emit each incoming message as a JSON line instead of formatted text
+    incoming_emojis: Option<Arc<EmojiConfig>>, // expand shortcodes in what others say, if enabled
+    ctcp_version: Option<String>, // answer CTCP requests, with this as our VERSION
+    show_ctcp: bool,         // show CTCP requests instead of handling them quietly
+    show_motd: bool, // show the MOTD sent on connect (one asked for with /motd always shows)
+    invite_autojoin: Option<Vec<String>>, // join when invited by someone matching these (empty: anyone)
+}
+
+/// Connects with `config` and spawns the task that listens to the server.
+/// `reconnecting` only changes how joining the config's channels is reported.
+async fn connect_and_listen(
+    mut config: Config,
+    ctx: ListenContext,
+    reconnecting: bool,
 ) -> Result<Arc<Mutex<Client>>> {
-    let mut client = Client::from_config(config).await?;
+    // The listener joins these itself once registered. Left to the irc crate,
+    // they'd be joined again at the end of every /motd.
+    let rejoin: Vec<(String, Option<String>)> = std::mem::take(&mut config.channels)
+        .into_iter()
+        .map(|channel| {
+            let key = config.channel_keys.remove(&channel);
+            (channel, key)
+        })
+        .collect();
+    let server = config.server.clone();
+    let client = Client::from_config(config).await?;
+    if let Ok(mut status) = ctx.status.lock() {
+        status.server = server;
+    }
+    // Ask for `@time` tags so bouncer playback keeps its original timestamps.
+    client.send_cap_req(&[Capability::ServerTime])?;
     client.identify()?;
 
+    let ListenContext {
+        irc_tx,
+        input_tx,
+        pending,
+        pings,
+        ignores,
+        status,
+        reconnect_on_error,
+        ping_interval,
+        ping_timeout,
+        heartbeat,
+        json_events,
+        incoming_emojis,
+        ctcp_version,
+        show_ctcp,
+        show_motd,
+        invite_autojoin,
+    } = ctx;
     let client = Arc::new(Mutex::new(client));
     let client_clone = Arc::clone(&client);
-    let irc_tx_clone = irc_tx.clone();
+    let events_tx = irc_tx.clone();
+    // In JSON mode the events replace the formatted lines, so those go nowhere.
+    let irc_tx_clone = if json_events {
+        mpsc::channel::<app::Message>(1).0
+    } else {
+        irc_tx.clone()
+    };
     let input_tx_clone = input_tx.clone();
 
     tokio::spawn(async move {
@@ -267,51 +1599,344 @@ async fn connect_and_listen(
             Ok(s) => s,
             Err(e) => {
                 let _ = irc_tx_clone
-                    .send(format!("Error getting IRC stream: {}", e))
+                    .send(app::Message::error(format!(
+                        "Error getting IRC stream: {}",
+                        e
+                    )))
+                    .await;
+                let _ = input_tx_clone
+                    .send(InputCommand::Disconnected { reconnect: true })
                     .await;
-                let _ = input_tx_clone.send(InputCommand::Disconnected).await;
                 return;
             }
         };
+        let mut listed = 0usize; // channels received for the current /list
+        let mut motd: Vec<String> = Vec::new(); // lines of the MOTD being received
+        let mut motd_shown = show_motd;
+        let mut registered = false; // the first end of the MOTD has been seen
+
+        // After /disconnect the IRC task drops its handle, leaving this task the
+        // only owner; the closing connection then isn't worth reporting.
+        let abandoned = || Arc::strong_count(&client_clone) == 1;
+
+        // Our own keepalive: PING after a quiet spell, give up if still nothing.
+        let mut keepalive = tokio::time::interval(Duration::from_secs(1));
+        let mut last_seen = Instant::now();
+        let mut ping_sent = false;
+        // And, if configured, a PING on a fixed schedule to keep NATs and idle timeouts happy.
+        let period = heartbeat.unwrap_or(Duration::from_secs(3600));
+        let mut beat = tokio::time::interval_at(Instant::now() + period, period);
         loop {
             select! {
+                _ = beat.tick(), if heartbeat.is_some() => {
+                    let _ = client_clone.lock().await.send(Command::PING("meow".to_string(), None));
+                }
+                _ = keepalive.tick() => {
+                    let quiet = last_seen.elapsed();
+                    if quiet >= ping_interval + ping_timeout {
+                        let _ = irc_tx_clone.send("*** Connection timed out".into()).await;
+                        if !abandoned() {
+                            let _ = input_tx_clone.send(InputCommand::Disconnected { reconnect: true }).await;
+                        }
+                        break;
+                    } else if quiet >= ping_interval && !ping_sent {
+                        ping_sent = true;
+                        let _ = client_clone.lock().await.send(Command::PING("meow".to_string(), None));
+                    }
+                }
                 // Handle IRC messages
                 maybe_message = stream.next() => {
                     if let Some(Ok(message)) = maybe_message {
+                        last_seen = Instant::now();
+                        ping_sent = false;
+                        let Some(message) = sanitize(message) else { continue };
+                        if matches!(message.command, Command::PRIVMSG(..) | Command::NOTICE(..)) && is_ignored(&ignores, &message) {
+                            continue;
+                        }
+                        if json_events {
+                            let _ = events_tx.send(app::Message::new(MessageKind::Json, headless::event_json(&message))).await;
+                        }
+                        let stamped = Stamped { tx: &irc_tx_clone, time: server_time(&message) };
+                        if let Command::Response(Response::RPL_MOTDSTART | Response::ERR_NOMOTD, _) = message.command {
+                            // Checked before the reply clears it: a /motd is shown even when show_motd is off.
+                            motd_shown = show_motd || pending.lock().is_ok_and(|p| p.iter().any(|p| p.command == "/motd"));
+                        }
+                        if let Command::Response(resp, _) = &message.command {
+                            if let Ok(mut pending) = pending.lock() {
+                                pending.retain(|p| !p.replies.contains(resp));
+                            }
+                        }
+                        if matches!(message.command, Command::Response(Response::RPL_NAMREPLY, _) | Command::JOIN(..) | Command::PART(..) | Command::KICK(..) | Command::QUIT(_) | Command::NICK(_)) {
+                            let own_nick = own_nick(&status, &client_clone).await;
+                            track_members(&status, &message, &own_nick);
+                        }
                         match message.command {
-                            Command::PRIVMSG(target, msg) => {
-                                if let Some(ref prefix) = message.prefix {
-                                    let prefix_str = prefix.to_string();
-                                    let parts: Vec<&str> = prefix_str.split('!').collect();
-                                    let nick = parts[0];
-
-
-                                    let color_code = if let Some(crossterm::style::Color::Rgb { r, g, b }) = accent_color {
-                                        format!("38;2;{};{};{}", r, g, b)
-                                    } else {
-                                        "38;2;128;0;128".to_string() // Default purple
+                            // CTCP requests (other than ACTION) get an automatic NOTICE reply, not a chat line.
+                            Command::PRIVMSG(_, ref msg) if msg.starts_with('\x01') && !msg.starts_with("\x01ACTION ") => {
+                                let Some(nick) = message.source_nickname() else { continue };
+                                let request = msg.trim_matches('\x01');
+                                let reply = ctcp_version.as_deref().and_then(|version| ctcp_reply(request, version));
+                                if let Some(reply) = &reply {
+                                    let _ = client_clone.lock().await.send_notice(nick, format!("\x01{}\x01", reply));
+                                }
+                                if show_ctcp {
+                                    let command = request.split(' ').next().unwrap_or("");
+                                    let answered = if reply.is_some() { "answered" } else { "not answered" };
+                                    let _ = stamped.send(format!("*** CTCP {} from {} ({})", command, nick, answered).into()).await;
+                                }
+                            }
+                            Command::PRIVMSG(ref target, ref msg) => {
+                                if let Some(nick) = message.source_nickname() {
+                                    // A private message belongs to the conversation with its sender.
+                                    let conversation = if target.starts_with(['#', '&', '+', '!']) { target } else { nick };
+                                    let text = match &incoming_emojis {
+                                        Some(emojis) => emojis.expand(msg),
+                                        None => msg.clone(),
                                     };
-
-                                    let _ = irc_tx_clone.send(format!("\x1b[1m\x1b[{}m<{}>\x1b[0m {}", color_code, nick, msg)).await;
+                                    let line = match text.strip_prefix("\x01ACTION ") {
+                                        Some(action) => app::Message::new(MessageKind::Action, action.trim_end_matches('\x01')),
+                                        None => app::Message::new(MessageKind::Privmsg, text),
+                                    };
+                                    let _ = stamped.send(line.with_sender(nick, conversation)).await;
+                                }
+                            }
+                            Command::TOPIC(ref channel, Some(ref topic)) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                set_topic(&status, channel, topic);
+                                let _ = stamped.send(format!("*** {} changed the topic of {} to: {}", nick, channel, topic).into()).await;
+                            }
+                            Command::Response(Response::RPL_TOPIC, ref args) if args.len() >= 3 => {
+                                set_topic(&status, &args[1], &args[2]);
+                                let _ = stamped.send(format!("*** Topic for {}: {}", args[1], args[2]).into()).await;
+                            }
+                            Command::Response(Response::RPL_NOTOPIC, ref args) if args.len() >= 2 => {
+                                set_topic(&status, &args[1], "");
+                            }
+                            Command::Response(Response::RPL_WELCOME, ref args) if !args.is_empty() => {
+                                if let Ok(mut status) = status.lock() {
+                                    status.nick = Some(args[0].clone());
+                                }
+                                let _ = stamped.send(message.to_string().into()).await;
+                            }
+                            Command::Response(Response::RPL_MOTDSTART, _) => motd.clear(),
+                            Command::Response(Response::RPL_MOTD, ref args) => {
+                                // Servers start each line with "- ".
+                                let line = args.last().map_or("", |l| l.strip_prefix('-').unwrap_or(l));
+                                motd.push(line.strip_prefix(' ').unwrap_or(line).to_string());
+                            }
+                            Command::Response(Response::RPL_ENDOFMOTD, _) | Command::Response(Response::ERR_NOMOTD, _) => {
+                                if motd_shown {
+                                    if motd.is_empty() {
+                                        let _ = stamped.send("*** The server has no MOTD".into()).await;
+                                    } else {
+                                        let _ = stamped.send("--- MOTD ---".into()).await;
+                                        for line in motd.drain(..) {
+                                            let _ = stamped.send(line.into()).await;
+                                        }
+                                    }
+                                }
+                                motd.clear();
+                                // Registration is over: join the channels, once per connection.
+                                if !std::mem::replace(&mut registered, true) && !rejoin.is_empty() {
+                                    let channels: Vec<&str> = rejoin.iter().map(|(c, _)| c.as_str()).collect();
+                                    if !reconnecting {
+                                        let _ = stamped.send(format!("*** Joining {}", channels.join(" ")).into()).await;
+                                    }
+                                    let client = client_clone.lock().await;
+                                    for (channel, key) in &rejoin {
+                                        let _ = client.send(Command::JOIN(channel.clone(), key.clone(), None));
+                                    }
+                                    drop(client);
+                                    if reconnecting {
+                                        let _ = stamped.send(format!("*** Rejoined {}", channels.join(" ")).into()).await;
+                                    }
                                 }
                             }
+                            Command::Response(Response::ERR_NICKNAMEINUSE, ref args) if args.len() >= 2 => {
+                                // The client has already sent NICK with the next alternative.
+                                let next = client_clone.lock().await.current_nickname().to_string();
+                                let _ = stamped.send(format!("*** Nick {} in use, trying {}", args[1], next).into()).await;
+                            }
+                            Command::JOIN(ref channel, _, _) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let text = format!("{} has joined {}", describe_source(&message), channel);
+                                let _ = stamped.send(app::Message::new(MessageKind::Join, text).with_sender(nick, channel)).await;
+                            }
+                            Command::PART(ref channel, ref reason) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let reason = reason.as_deref().filter(|r| !r.is_empty()).map(|r| format!(" ({})", r)).unwrap_or_default();
+                                let text = format!("{} has left {}{}", nick, channel, reason);
+                                let _ = stamped.send(app::Message::new(MessageKind::Part, text).with_sender(nick, channel)).await;
+                            }
+                            Command::KICK(ref channel, ref kicked, ref reason) if *kicked == own_nick(&status, &client_clone).await => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let reason = reason.as_deref().filter(|r| !r.is_empty()).map(|r| format!(" ({})", r)).unwrap_or_default();
+                                let _ = stamped.send(format!("*** You were kicked from {} by {}{}", channel, nick, reason).into()).await;
+                                let _ = input_tx_clone.send(InputCommand::Kicked(channel.clone())).await;
+                            }
+                            Command::NICK(ref new_nick) => {
+                                // If it was ours, track_members has already switched to the new nick.
+                                let nick = message.source_nickname().unwrap_or("server");
+                                if *new_nick == own_nick(&status, &client_clone).await {
+                                    let _ = stamped.send(format!("*** You are now known as {}", new_nick).into()).await;
+                                } else {
+                                    let mut line = app::Message::new(MessageKind::Nick, new_nick.as_str());
+                                    line.sender = Some(nick.to_string());
+                                    let _ = stamped.send(line).await;
+                                }
+                            }
+                            Command::QUIT(ref reason) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let reason = reason.as_deref().filter(|r| !r.is_empty()).map(|r| format!(" ({})", r)).unwrap_or_default();
+                                let mut line = app::Message::new(MessageKind::Quit, format!("{} has quit{}", nick, reason));
+                                line.sender = Some(nick.to_string());
+                                let _ = stamped.send(line).await;
+                            }
+                            Command::NOTICE(ref target, ref msg) => {
+                                // Notices from the server itself (including the ones before registration,
+                                // sent to `*` or `AUTH`) carry its name rather than a nick. They aren't tied
+                                // to a channel, so they stay with the status lines.
+                                let line = app::Message::new(MessageKind::Notice, msg.as_str());
+                                let line = match (message.source_nickname(), &message.prefix) {
+                                    (Some(from), _) if target.starts_with(['#', '&', '+', '!']) => line.with_sender(from, target),
+                                    (Some(from), _) => line.with_sender(from, from),
+                                    (None, Some(Prefix::ServerName(server))) => line.with_sender(server, target),
+                                    (None, _) => line,
+                                };
+                                let _ = stamped.send(line).await;
+                            }
+                            Command::INVITE(_, ref channel) => {
+                                let from = message.source_nickname().unwrap_or("server");
+                                let source = match &message.prefix {
+                                    Some(Prefix::Nickname(nick, user, host)) => format!("{}!{}@{}", nick, user, host),
+                                    _ => from.to_string(),
+                                };
+                                if let Ok(mut status) = status.lock() {
+                                    status.invite = Some(channel.clone());
+                                }
+                                let accept = invite_autojoin
+                                    .as_ref()
+                                    .is_some_and(|masks| masks.is_empty() || masks.iter().any(|m| wildcard_match(m, &source)));
+                                let line = if accept {
+                                    let _ = input_tx_clone.send(InputCommand::JoinChannel(channel.clone(), None)).await;
+                                    format!("*** {} invited you to {}, joining", from, channel)
+                                } else {
+                                    format!("*** {} invited you to {} (type /join to accept)", from, channel)
+                                };
+                                let _ = stamped.send(line.into()).await;
+                            }
+                            Command::WALLOPS(ref text) => {
+                                let from = match &message.prefix {
+                                    Some(Prefix::Nickname(nick, _, _)) => nick.as_str(),
+                                    Some(Prefix::ServerName(server)) => server.as_str(),
+                                    None => "server",
+                                };
+                                let line = app::Message::new(MessageKind::Wallops, text.as_str());
+                                let _ = stamped.send(line.with_sender(from, from)).await;
+                            }
+                            Command::Response(Response::RPL_NOWAWAY, _) => {
+                                let auto = status.lock().is_ok_and(|mut s| {
+                                    s.away = true;
+                                    s.auto_away
+                                });
+                                let line = if auto { "*** Auto-away (idle)" } else { "*** You are now marked as away" };
+                                let _ = stamped.send(line.into()).await;
+                            }
+                            Command::Response(Response::RPL_UNAWAY, _) => {
+                                let auto = status.lock().is_ok_and(|mut s| {
+                                    s.away = false;
+                                    std::mem::take(&mut s.auto_away)
+                                });
+                                let line = if auto { "*** Back" } else { "*** You are no longer away" };
+                                let _ = stamped.send(line.into()).await;
+                            }
+                            Command::Response(Response::RPL_AWAY, ref args) if args.len() >= 3 => {
+                                let _ = stamped.send(format!("*** {} is away: {}", args[1], args[2]).into()).await;
+                            }
+                            Command::ChannelMODE(ref channel, ref modes) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let _ = stamped.send(format!("*** {} sets mode {} on {}", nick, join_modes(modes), channel).into()).await;
+                            }
+                            Command::UserMODE(ref target, ref modes) => {
+                                let nick = message.source_nickname().unwrap_or("server");
+                                let _ = stamped.send(format!("*** {} sets mode {} on {}", nick, join_modes(modes), target).into()).await;
+                            }
+                            Command::Response(Response::RPL_CHANNELMODEIS, ref args) if args.len() >= 3 => {
+                                let _ = stamped.send(format!("*** Modes for {}: {}", args[1], args[2..].join(" ")).into()).await;
+                            }
+                            Command::Response(Response::ERR_CHANOPRIVSNEEDED, ref args) if args.len() >= 2 => {
+                                let _ = stamped.send(format!("*** You're not a channel operator on {}", args[1]).into()).await;
+                            }
+                            Command::Response(Response::RPL_LISTSTART, _) => {
+                                listed = 0;
+                                let _ = stamped.send("*** Channel list:".into()).await;
+                            }
+                            Command::Response(Response::RPL_LIST, ref args) if args.len() >= 3 => {
+                                let topic = args.get(3).map(String::as_str).unwrap_or("");
+                                let _ = stamped.send(format!("{} ({} users): {}", args[1], args[2], topic).into()).await;
+                                listed += 1;
+                                // Big networks send thousands of these; let the UI keep up.
+                                if listed.is_multiple_of(50) {
+                                    sleep(Duration::from_millis(20)).await;
+                                }
+                            }
+                            Command::Response(Response::RPL_LISTEND, _) => {
+                                let _ = stamped.send(format!("*** End of channel list ({} channels)", listed).into()).await;
+                            }
+                            Command::PONG(ref server, ref token) => {
+                                // Servers echo our token as the last parameter. Keepalive
+                                // answers aren't in the map; hearing them is all that matters.
+                                let token = token.as_ref().unwrap_or(server);
+                                let sent = pings.lock().ok().and_then(|mut p| p.remove(token));
+                                if let Some(sent) = sent {
+                                    let lag = sent.elapsed();
+                                    if let Ok(mut status) = status.lock() {
+                                        status.lag = Some(lag);
+                                    }
+                                    let _ = stamped.send(format!("*** Lag: {}ms", lag.as_millis()).into()).await;
+                                }
+                            }
+                            Command::CAP(..) => {
+                                // Our server-time request being acked (or refused); nothing to show.
+                            }
                             Command::PING(param, _) => {
                                 // Respond to PING to keep the connection alive
                                 let _ = client_clone.lock().await.send_pong(&param);
                             }
                             Command::ERROR(e) => {
-                                let _ = irc_tx_clone.send(format!("IRC Error: {}", e)).await;
-                                let _ = input_tx_clone.send(InputCommand::Disconnected).await; // Signal disconnection
+                                // Bans won't go away by retrying, so don't hammer the server.
+                                let banned = is_ban_error(&e);
+                                if banned {
+                                    let _ = stamped.send(format!("*** Banned from server: {}", e).into()).await;
+                                } else {
+                                    let _ = stamped.send(app::Message::error(format!("IRC Error: {}", e))).await;
+                                }
+                                let reconnect = reconnect_on_error && !banned;
+                                if !abandoned() {
+                                    let _ = input_tx_clone.send(InputCommand::Disconnected { reconnect }).await; // Signal disconnection
+                                }
                                 break; // Exit message processing loop on error
                             }
                             _ => {
                                 // For other messages, just display them as is for now.
-                                let _ = irc_tx_clone.send(format!("{}", message.to_string())).await;
+                                let _ = stamped.send(message.to_string().into()).await;
                             }
                         }
+                    } else if let Some(Err(irc::error::Error::PingTimeout)) = maybe_message {
+                        let _ = irc_tx_clone.send("*** Connection timed out".into()).await;
+                        if !abandoned() {
+                            let _ = input_tx_clone.send(InputCommand::Disconnected { reconnect: true }).await;
+                        }
+                        break;
+                    } else if let Some(Err(irc::error::Error::NoUsableNick)) = maybe_message {
+                        let _ = irc_tx_clone.send("*** Every nick tried is in use. Set alt_nicks or /connect with another nick.".into()).await;
+                        let _ = input_tx_clone.send(InputCommand::Disconnected { reconnect: false }).await;
+                        break;
                     } else {
                         // Stream ended, meaning disconnected.
-                        let _ = input_tx_clone.send(InputCommand::Disconnected).await; // Signal disconnection
+                        if !abandoned() {
+                            let _ = input_tx_clone.send(InputCommand::Disconnected { reconnect: true }).await; // Signal disconnection
+                        }
                         break; // Exit message processing loop
                     }
                 }
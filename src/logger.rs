@@ -0,0 +1,179 @@
+use crate::app::Message;
+use crate::config::format_time;
+use chrono::Local;
+use std::path::PathBuf;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::select;
+use tokio::sync::mpsc::{self, Receiver};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, Duration};
+
+/// Removes ANSI escape sequences and mIRC formatting codes so log files
+/// (and anything else that wants plain text) don't carry terminal noise.
+pub fn strip_formatting(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.peek() {
+                // CSI sequences end at the first alphabetic character.
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                }
+                // OSC sequences (hyperlinks) end at BEL or `ESC \`.
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' {
+                            break;
+                        }
+                        if c == '\x1b' {
+                            chars.next_if_eq(&'\\');
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\x03' => {
+                // mIRC color: up to two foreground digits, optionally ",bg".
+                for _ in 0..2 {
+                    if chars.next_if(|c| c.is_ascii_digit()).is_none() {
+                        break;
+                    }
+                }
+                let mut ahead = chars.clone();
+                if ahead.next() == Some(',') && ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    chars.next();
+                    for _ in 0..2 {
+                        if chars.next_if(|c| c.is_ascii_digit()).is_none() {
+                            break;
+                        }
+                    }
+                }
+            }
+            '\x02' | '\x0f' | '\x16' | '\x1d' | '\x1e' | '\x1f' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Works out which channel or nick a displayed line belongs to, so each log
+/// line can say where it came from. Lines that aren't tied to a target
+/// (status, errors) are logged under `*`.
+fn line_target(line: &str) -> &str {
+    if let Some(rest) = line.strip_prefix('<') {
+        let header = rest.split('>').next().unwrap_or("");
+        if let (Some(open), Some(close)) = (header.find('('), header.rfind(')')) {
+            return &header[open + 1..close];
+        }
+        // A bare `<nick>` is a private message from that nick.
+        return header;
+    }
+    for marker in ["*** Joined ", "*** Left "] {
+        if let Some(channel) = line.strip_prefix(marker) {
+            return channel.trim();
+        }
+    }
+    // Other users: `*** nick (user@host) has joined #chan`, `*** nick has left #chan (reason)`
+    for marker in [" has joined ", " has left "] {
+        if let Some((_, rest)) = line.split_once(marker) {
+            return rest.split_whitespace().next().unwrap_or("*");
+        }
+    }
+    "*"
+}
+
+/// `dir` with a leading `~/` replaced by the home directory.
+pub fn expand_home(dir: &str) -> PathBuf {
+    match dir.strip_prefix("~/") {
+        Some(rest) => dirs::home_dir()
+            .map(|home| home.join(rest))
+            .unwrap_or_else(|| PathBuf::from(dir)),
+        None => PathBuf::from(dir),
+    }
+}
+
+/// Sits between the IRC client and the UI, appending every displayed line to
+/// a per-session log file in `log_dir` and forwarding it on unchanged.
+/// Writes are buffered and flushed every few seconds so the UI never waits
+/// on the disk. The task flushes and ends once the UI stops listening, so
+/// awaiting its handle on the way out keeps the end of the log. With `utc`,
+/// times are written in UTC, matching `timestamps_utc` on screen.
+pub fn spawn(
+    log_dir: &str,
+    utc: bool,
+    mut rx: Receiver<Message>,
+) -> (Receiver<Message>, JoinHandle<()>) {
+    let (tx, forwarded) = mpsc::channel::<Message>(100);
+    let dir = expand_home(log_dir);
+
+    let task = tokio::spawn(async move {
+        let path = dir.join(format!(
+            "meow-{}.log",
+            Local::now().format("%Y-%m-%d_%H-%M-%S")
+        ));
+        let file = match fs::create_dir_all(&dir).await {
+            Ok(()) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        let mut writer = match file {
+            Ok(file) => Some(BufWriter::new(file)),
+            Err(e) => {
+                let _ = tx
+                    .send(Message::error(format!(
+                        "Error opening log file {}: {}",
+                        path.display(),
+                        e
+                    )))
+                    .await;
+                None
+            }
+        };
+
+        let mut flush = interval(Duration::from_secs(5));
+        loop {
+            select! {
+                maybe_line = rx.recv() => {
+                    let Some(line) = maybe_line else { break };
+                    if let Some(w) = writer.as_mut() {
+                        let plain = strip_formatting(&line.to_string());
+                        let target = line.target.as_deref().unwrap_or_else(|| line_target(&plain));
+                        let entry = format!(
+                            "[{}] [{}] {}\n",
+                            format_time(&line.timestamp, "%Y-%m-%d %H:%M:%S", utc),
+                            target,
+                            plain
+                        );
+                        let _ = w.write_all(entry.as_bytes()).await;
+                    }
+                    if tx.send(line).await.is_err() {
+                        break;
+                    }
+                }
+                _ = flush.tick() => {
+                    if let Some(w) = writer.as_mut() {
+                        let _ = w.flush().await;
+                    }
+                }
+                _ = tx.closed() => break,
+            }
+        }
+
+        if let Some(w) = writer.as_mut() {
+            let _ = w.flush().await;
+        }
+    });
+
+    (forwarded, task)
+}
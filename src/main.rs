@@ -1,11 +1,17 @@
 mod app;
 mod config;
+mod headless;
+mod history;
 mod irc_client;
+mod logger;
+mod notify;
 mod ui;
+mod wildcard;
 
 use anyhow::Result;
-use app::InputCommand;
+use app::{InputCommand, SharedStatus};
 use crossterm::{
+    event::{DisableBracketedPaste, DisableFocusChange, DisableMouseCapture},
     execute,
     terminal::{disable_raw_mode, LeaveAlternateScreen},
 };
@@ -15,67 +21,164 @@ use crate::config::UserConfig;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Flush welcome message before UI takes over
-    print_welcome_box();
-    std::io::stdout().flush()?; // <-- flush to force immediate draw
+    // --json / --headless: no terminal UI, events go to stdout as JSON lines
+    let headless = std::env::args().any(|arg| arg == "--json" || arg == "--headless");
 
-    // Pause for 2 seconds to allow the user to see the welcome box
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    // --config <path>: read the config from somewhere other than the default
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            match args.next() {
+                Some(path) => UserConfig::set_path(path.into()),
+                None => anyhow::bail!("--config needs a path"),
+            }
+        } else if let Some(path) = arg.strip_prefix("--config=") {
+            UserConfig::set_path(path.into());
+        }
+    }
+
+    // --init [--force]: write a starter config and exit
+    if std::env::args().any(|arg| arg == "--init") {
+        let force = std::env::args().any(|arg| arg == "--force");
+        match UserConfig::write_template(force) {
+            Ok(path) => println!("Wrote a starter config to {}", path.display()),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let startup_config = UserConfig::load().unwrap_or_default();
+    let skip_welcome = startup_config.ui.as_ref().and_then(|u| u.skip_welcome) == Some(true);
+    if !headless && !skip_welcome {
+        // Flush welcome message before UI takes over
+        print_welcome_box(startup_config.ascii());
+        std::io::stdout().flush()?; // <-- flush to force immediate draw
+
+        // Pause for 2 seconds to allow the user to see the welcome box
+        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    }
 
     // Create communication channels
-    let (irc_tx, ui_rx) = mpsc::channel::<String>(100);
+    let (irc_tx, ui_rx) = mpsc::channel::<app::Message>(100);
     let (ui_tx, input_rx) = mpsc::channel::<InputCommand>(100);
+    let status = SharedStatus::default();
+
+    // Ctrl+C, or SIGINT from elsewhere (e.g. `kill -INT`), leaves the server
+    // cleanly like /quit. In the terminal UI raw mode turns the key itself
+    // into a keypress, which the UI handles the same way.
+    tokio::spawn({
+        let ui_tx = ui_tx.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                let _ = ui_tx.send(InputCommand::Quit(None)).await;
+            }
+        }
+    });
 
-    let config = UserConfig::load();
+    // A broken config falls back to defaults; say why instead of doing it silently.
+    // TOML errors span several lines (position, snippet, caret), so send each one.
+    let config = match UserConfig::read() {
+        Ok(config) => config,
+        Err(e) => {
+            let path = UserConfig::config_path();
+            irc_tx.send(app::Message::error(format!("Error in config {}, using defaults:", path.display()))).await?;
+            for line in e.to_string().lines().filter(|l| !l.trim().is_empty()) {
+                irc_tx.send(app::Message::error(line.to_string())).await?;
+            }
+            None
+        }
+    };
     let accent_color_hex = config
         .as_ref()
         .and_then(|cfg| cfg.theme.as_ref()?.accent.clone());
 
+    // Route displayed lines through the session logger when a log_dir is set
+    let (ui_rx, log_task) = match config.as_ref().and_then(|cfg| cfg.irc.as_ref()?.log_dir.clone()) {
+        Some(dir) => {
+            let utc = config.as_ref().and_then(|cfg| cfg.ui.as_ref()).is_some_and(|ui| ui.timestamps_utc());
+            let (rx, task) = logger::spawn(&dir, utc, ui_rx);
+            (rx, Some(task))
+        }
+        None => (ui_rx, None),
+    };
+
     // Spawn IRC logic
     let irc_handle = tokio::spawn({
         let ui_tx = ui_tx.clone();
-        let accent_color_hex_for_irc = accent_color_hex.clone();
+        let status = status.clone();
         async move {
-            if let Err(e) = irc_client::run_irc(irc_tx, ui_tx, input_rx, accent_color_hex_for_irc).await {
+            if let Err(e) = irc_client::run_irc(irc_tx, ui_tx, input_rx, status, headless).await {
                 eprintln!("IRC client error: {:?}", e);
             }
         }
     });
 
+    if headless {
+        if let Err(e) = headless::run_headless(ui_tx, ui_rx).await {
+            eprintln!("Headless error: {:?}", e);
+        }
+        if let Some(task) = log_task {
+            let _ = task.await;
+        }
+        irc_handle.await?;
+        // After a Ctrl+C stdin may still be mid-read on a blocking thread, which
+        // the runtime would wait for on the way out; there's nothing left to read.
+        std::process::exit(0);
+    }
+
     // Run the terminal UI
-    if let Err(e) = ui::run_ui(ui_tx, ui_rx, accent_color_hex).await {
+    if let Err(e) = ui::run_ui(ui_tx, ui_rx, accent_color_hex, status).await {
         eprintln!("UI error: {:?}", e);
     }
 
     // Clean up terminal
-    disable_raw_mode()?;
-    execute!(stdout(), LeaveAlternateScreen)?;
+    restore_terminal();
+    // Then let the logger write out what's left before the runtime goes away.
+    if let Some(task) = log_task {
+        let _ = task.await;
+    }
     irc_handle.await?;
 
     Ok(())
 }
 
+/// Puts the terminal back the way the shell left it, however meow is exiting.
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(
+        stdout(),
+        DisableBracketedPaste,
+        DisableFocusChange,
+        DisableMouseCapture,
+        LeaveAlternateScreen,
+        cursor::Show
+    );
+}
+
 use crossterm::{
     cursor,
     style::{Color, ResetColor, SetForegroundColor},
     ExecutableCommand,
 };
 
-pub fn print_welcome_box() {
+pub fn print_welcome_box(ascii: bool) {
     let mut out = stdout();
 
     let lines = [
-        "┌──────────────────────────────────────────────────┐",
-        "⎹              Welcome to meow IRC Client          ⎹",
-        "+--------------------------------------------------+",
-        "⎹ Available Commands:                              ⎹",
-        "⎹                                                  ⎹",
-        "⎹  /connect <server> <port> <nick> <tls?>          ⎹",
-        "⎹  /join <#channel>                                ⎹",
-        "⎹  /part <#channel>                                ⎹",
-        "⎹  /msg <target> <message>                         ⎹",
-        "⎹  /quit                                           ⎹",
-        "└─────────────────────────────────────────────────┘",
+        "┌────────────────────────────────────────────────────────────┐",
+        "⎹              Welcome to meow IRC Client                    ⎹",
+        "+------------------------------------------------------------+",
+        "⎹ Available Commands:                                        ⎹",
+        "⎹                                                            ⎹",
+        &format!("⎹  {:<58}⎹", ui::CONNECT_USAGE),
+        &format!("⎹  {:<58}⎹", "/join <#channel>"),
+        &format!("⎹  {:<58}⎹", "/part <#channel>"),
+        &format!("⎹  {:<58}⎹", "/msg <target> <message>"),
+        &format!("⎹  {:<58}⎹", "/quit"),
+        "└────────────────────────────────────────────────────────────┘",
         "",
     ];
 
@@ -84,7 +187,11 @@ pub fn print_welcome_box() {
     let _ = out.execute(SetForegroundColor(Color::Cyan));
     for (i, line) in lines.iter().enumerate() {
         let _ = out.execute(cursor::MoveTo(5, start_y + i as u16));
-        println!("{}", line);
+        if ascii {
+            println!("{}", ui::asciify(line));
+        } else {
+            println!("{}", line);
+        }
     }
     let _ = out.execute(ResetColor);
     let _ = out.flush();
@@ -0,0 +1,59 @@
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// The shortest gap between two notifications, so a flood of highlights
+/// doesn't bury the desktop in popups.
+const MIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Desktop notifications for highlights, at most one per `MIN_INTERVAL`.
+#[derive(Default)]
+pub struct Notifier {
+    last: Option<Instant>,
+}
+
+impl Notifier {
+    pub fn notify(&mut self, title: &str, body: &str) {
+        if self.last.is_some_and(|last| last.elapsed() < MIN_INTERVAL) {
+            return;
+        }
+        self.last = Some(Instant::now());
+        if let Some(mut command) = command(title, body) {
+            let spawned = command
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .spawn();
+            // Reap it off the UI thread; a missing tool or desktop isn't worth reporting.
+            if let Ok(mut child) = spawned {
+                std::thread::spawn(move || child.wait());
+            }
+        }
+    }
+}
+
+/// The platform's own notification tool, called to show `title` and `body`.
+#[cfg(target_os = "macos")]
+fn command(title: &str, body: &str) -> Option<Command> {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        quote(body),
+        quote(title)
+    ));
+    Some(command)
+}
+
+/// The platform's own notification tool, called to show `title` and `body`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn command(title: &str, body: &str) -> Option<Command> {
+    let mut command = Command::new("notify-send");
+    command.arg("--app-name=meow").arg(title).arg(body);
+    Some(command)
+}
+
+/// No notification tool to call here.
+#[cfg(not(unix))]
+fn command(_title: &str, _body: &str) -> Option<Command> {
+    None
+}
@@ -1,163 +1,1491 @@
-use crate::app::InputCommand;
-use crate::config::UserConfig;
+use crate::app::{InputCommand, Message, MessageKind, SharedStatus, Status};
+use crate::config::{
+    default_port, fg_code, format_time, parse_color, ConfigWatcher, HighlightWord, IrcConfig,
+    UserConfig, DEFAULT_MUTED, DEFAULT_PROMPT,
+};
+use crate::history;
+use crate::logger::strip_formatting;
+use crate::notify::Notifier;
+use crate::wildcard::wildcard_match;
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode},
-    execute,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableFocusChange,
+        EnableMouseCapture, Event, KeyCode, KeyModifiers, MouseEventKind,
+    },
+    execute, queue,
     style::{Attribute, Color, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
-    terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen},
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
-use std::collections::VecDeque;
+#[cfg(unix)]
+use futures::FutureExt;
+use std::collections::{HashMap, VecDeque};
 use std::io::{stdout, Write};
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// The input line with the cursor drawn as a reverse-video cell at byte
+/// offset `cursor`.
+fn with_cursor(input: &str, cursor: usize) -> String {
+    let (before, after) = input.split_at(cursor);
+    let mut rest = after.chars();
+    let at = rest.next().unwrap_or(' ');
+    format!("{}\x1b[7m{}\x1b[27m{}", before, at, rest.as_str())
+}
+
+/// Where the word before `cursor` starts, skipping the spaces right before
+/// it, for Ctrl+W.
+fn previous_word_start(input: &str, cursor: usize) -> usize {
+    let before = input[..cursor].trim_end();
+    before
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// Most lines taken from the IRC task per frame, so a flood (say, a bouncer's
+/// backlog) can't hold up the keyboard; the rest wait for the next frame.
+const DRAIN_PER_FRAME: usize = 50;
+
+/// The lines waiting from the IRC task, up to `DRAIN_PER_FRAME` of them.
+fn take_lines(irc_rx: &mut Receiver<Message>) -> Vec<Message> {
+    let mut lines = Vec::new();
+    while lines.len() < DRAIN_PER_FRAME {
+        let Ok(line) = irc_rx.try_recv() else { break };
+        lines.push(line);
+    }
+    lines
+}
+
+/// How long a frame waits for a key after taking `drained` lines. With lines
+/// still waiting it only checks for one, so typing goes through mid-flood.
+fn key_wait(drained: usize) -> Duration {
+    if drained == DRAIN_PER_FRAME {
+        Duration::ZERO
+    } else {
+        Duration::from_millis(100)
+    }
+}
+
+/// How `/connect` is used, as the welcome screens show it.
+pub const CONNECT_USAGE: &str = "/connect [server|profile] [port] [nick] [tls] [password]";
+
+/// Turned in the status line while a /connect is in flight.
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Lines scrolled per mouse wheel notch.
+const MOUSE_SCROLL_LINES: usize = 2;
+
+/// Pastes with more lines than this ask before sending, unless configured.
+const DEFAULT_PASTE_CONFIRM_LINES: usize = 5;
+
+/// The non-blank lines of a paste, whatever line endings it used.
+fn paste_lines(text: &str) -> Vec<String> {
+    text.split(['\r', '\n'])
+        .filter(|l| !l.trim().is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Sends each pasted line as its own message to the current channel. The
+/// IRC task's flood protection spaces them out.
+async fn send_paste(
+    lines: Vec<String>,
+    input_tx: &Sender<InputCommand>,
+    messages: &mut VecDeque<Line>,
+) -> anyhow::Result<()> {
+    for line in lines {
+        push_line(messages, format!("You: {}", line).into());
+        input_tx.send(InputCommand::SendPlainMessage(line)).await?;
+    }
+    Ok(())
+}
+
+/// A Ctrl+R search through the input history.
+struct HistorySearch {
+    query: String,
+    found: Option<usize>, // index of the entry shown, if anything matches
+}
+
+/// The newest entry before index `before` that contains `query`, ignoring
+/// case. Called with the previous match to step back to older ones.
+fn search_history(history: &[String], query: &str, before: usize) -> Option<usize> {
+    if query.is_empty() {
+        return None;
+    }
+    let query = query.to_lowercase();
+    history[..before.min(history.len())]
+        .iter()
+        .rposition(|entry| entry.to_lowercase().contains(&query))
+}
+
+/// The `:partial` emoji shortcode being typed just before `cursor`, if any,
+/// as its start offset and the text after the colon.
+fn shortcode_at(input: &str, cursor: usize) -> Option<(usize, &str)> {
+    let before = &input[..cursor];
+    let start = before
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let partial = before[start..].strip_prefix(':')?;
+    (!partial.contains(':')).then_some((start, partial))
+}
+
+/// Configured emoji aliases in alphabetical order, for Tab completion.
+fn alias_names(config: Option<&UserConfig>) -> Vec<String> {
+    let mut names: Vec<String> = config
+        .and_then(|c| c.emojis.as_ref())
+        .map(|e| e.aliases.keys().cloned().collect())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+/// A slash command `run_ui` understands: how to call it and what it does.
+struct CommandHelp {
+    usage: &'static str,
+    about: &'static str,
+}
+
+impl CommandHelp {
+    fn name(&self) -> &'static str {
+        self.usage.split(' ').next().unwrap_or(self.usage)
+    }
+}
+
+/// Every slash command `run_ui` understands. Tab completion and `/help` are
+/// both built from this, so new commands should be added here as well as to
+/// the parser below.
+const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        usage: CONNECT_USAGE,
+        about: "connect to a server, or to a profile from the config",
+    },
+    CommandHelp {
+        usage: "/join <channel>[,channel...] [key[,key...]]",
+        about: "join one or more channels, with keys if they need them",
+    },
+    CommandHelp {
+        usage: "/part <channel>[,channel...]",
+        about: "leave one or more channels",
+    },
+    CommandHelp {
+        usage: "/msg <target>[,target...] <message>",
+        about: "send a private message, to several targets at once with commas",
+    },
+    CommandHelp {
+        usage: "/query <nick> [message]",
+        about: "open a private conversation with a nick",
+    },
+    CommandHelp {
+        usage: "/notice <target> <message>",
+        about: "send a notice",
+    },
+    CommandHelp {
+        usage: "/away [message]",
+        about: "mark yourself away, or back when there's no message",
+    },
+    CommandHelp {
+        usage: "/mode <target> [modes] [args]",
+        about: "show or change channel or user modes",
+    },
+    CommandHelp {
+        usage: "/kick [#channel] <nick> [reason]",
+        about: "remove a nick from a channel",
+    },
+    CommandHelp {
+        usage: "/ban [#channel] <mask>",
+        about: "ban a mask from a channel",
+    },
+    CommandHelp {
+        usage: "/op [#channel] <nick>",
+        about: "make a nick a channel operator",
+    },
+    CommandHelp {
+        usage: "/deop [#channel] <nick>",
+        about: "take operator status from a nick",
+    },
+    CommandHelp {
+        usage: "/voice [#channel] <nick>",
+        about: "give a nick voice",
+    },
+    CommandHelp {
+        usage: "/devoice [#channel] <nick>",
+        about: "take voice from a nick",
+    },
+    CommandHelp {
+        usage: "/whois <nick>",
+        about: "look up who a nick is",
+    },
+    CommandHelp {
+        usage: "/names [channel]",
+        about: "list the nicks in a channel",
+    },
+    CommandHelp {
+        usage: "/list [pattern]",
+        about: "list the channels on the server",
+    },
+    CommandHelp {
+        usage: "/ignore [nick|mask]",
+        about: "hide messages from a nick or a mask like baduser!*@*, or list ignores",
+    },
+    CommandHelp {
+        usage: "/unignore <nick|mask>",
+        about: "stop ignoring a nick or mask",
+    },
+    CommandHelp {
+        usage: "/motd",
+        about: "show the server's message of the day again",
+    },
+    CommandHelp {
+        usage: "/ping",
+        about: "measure the lag to the server",
+    },
+    CommandHelp {
+        usage: "/raw <line>",
+        about: "send a line to the server as it is",
+    },
+    CommandHelp {
+        usage: "/clear",
+        about: "clear the buffer",
+    },
+    CommandHelp {
+        usage: "/filter joins on|off",
+        about: "hide or show joins, parts and quits",
+    },
+    CommandHelp {
+        usage: "/colors on|off",
+        about: "show or strip colors and formatting in others' messages",
+    },
+    CommandHelp {
+        usage: "/reload",
+        about: "re-read the config file",
+    },
+    CommandHelp {
+        usage: "/reconnect",
+        about: "connect again to the last server",
+    },
+    CommandHelp {
+        usage: "/disconnect [message]",
+        about: "leave the server but keep meow open",
+    },
+    CommandHelp {
+        usage: "/quit [message]",
+        about: "leave the server and exit",
+    },
+    CommandHelp {
+        usage: "/help [command]",
+        about: "show this menu, or help for one command",
+    },
+];
+
+/// Key bindings listed at the bottom of `/help`.
+const KEY_HELP: &[(&str, &str)] = &[
+    ("Left/Right, Home/End", "move the cursor"),
+    ("Ctrl+A / Ctrl+E", "start / end of line"),
+    ("Ctrl+U / Ctrl+K", "delete to start / end"),
+    ("Ctrl+W", "delete previous word"),
+    ("Ctrl+R", "search input history"),
+    ("Ctrl+L", "redraw the screen"),
+    ("Esc / Ctrl+C", "quit"),
+    ("PgUp/PgDn", "scroll the buffer"),
+    ("Ctrl+Home / Ctrl+End", "oldest / newest line"),
+    ("Tab", "complete /commands, :emoji:"),
+];
+
+/// Width of the text inside the `/help` box.
+const HELP_WIDTH: usize = 45;
+
+/// `text` with its box-drawing and other decorative characters swapped for
+/// ASCII ones of the same width, for terminals without those glyphs.
+pub fn asciify(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '╭' | '╮' | '╰' | '╯' | '┌' | '┐' | '└' | '┘' | '├' | '┤' | '┬' | '┴' | '┼' => {
+                '+'
+            }
+            '─' | '—' => '-',
+            '│' | '⎹' => '|',
+            '❯' | '»' => '>',
+            '…' => '~',
+            c => c,
+        })
+        .collect()
+}
+
+/// Boxed rows for `text`, wrapped to fit with continuation lines indented
+/// by `indent`.
+fn help_rows(text: &str, indent: usize) -> Vec<String> {
+    wrap_text(text, HELP_WIDTH, indent)
+        .into_iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let pad = if i == 0 { 0 } else { indent };
+            format!("│ {:pad$}{:<width$}│", "", line, width = HELP_WIDTH - pad)
+        })
+        .collect()
+}
+
+/// The `/help` menu: every command's usage, then the key bindings.
+fn help_box() -> Vec<String> {
+    let mut lines = vec![
+        "╭───────────────────────────────────────────────╮".to_string(),
+        "│                   Help Menu                  │".to_string(),
+        "├───────────────────────────────────────────────┤".to_string(),
+    ];
+    for command in COMMANDS {
+        lines.extend(help_rows(command.usage, command.name().len() + 1));
+    }
+    lines.push("├───────────────────────────────────────────────┤".to_string());
+    for (keys, about) in KEY_HELP {
+        lines.extend(help_rows(&format!("{:<21} {}", keys, about), 22));
+    }
+    lines.push("╰───────────────────────────────────────────────╯".to_string());
+    lines
+}
+
+/// What `/help <command>` says about one command, with or without its `/`.
+fn command_help(name: &str) -> Option<String> {
+    let name = format!("/{}", name.trim_start_matches('/'));
+    COMMANDS
+        .iter()
+        .find(|c| c.name() == name)
+        .map(|c| format!("{} — {}", c.usage, c.about))
+}
+
+/// Color used for the user's own nick when someone else mentions it, unless
+/// the theme sets `own_nick_color`.
+const MENTION_COLOR: &str = "\x1b[1;33m";
+/// Color for the `*** WALLOPS from nick:` part of an operator announcement.
+const WALLOPS_COLOR: &str = "\x1b[1;35m";
+/// Color for the body of a line that mentions the user or a highlight word.
+const HIGHLIGHT_COLOR: &str = "\x1b[33m";
+
+/// Characters that may appear in an IRC nickname besides alphanumerics.
+fn is_nick_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "[]\\`_^{|}-".contains(c)
+}
+
+/// Byte ranges of each whole-word, case-insensitive occurrence of `word`.
+fn word_matches(text: &str, word: &str) -> Vec<(usize, usize)> {
+    if word.is_empty() {
+        return Vec::new();
+    }
+    if word.contains('*') {
+        return wildcard_word_matches(text, word);
+    }
+    let lower = text.to_ascii_lowercase();
+    let needle = word.to_ascii_lowercase();
+    let mut found = Vec::new();
+    let mut last = 0;
+    for (start, _) in lower.match_indices(&needle) {
+        let end = start + needle.len();
+        if start < last {
+            continue;
+        }
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        if before.is_some_and(is_nick_char) || after.is_some_and(is_nick_char) {
+            continue;
+        }
+        found.push((start, end));
+        last = end;
+    }
+    found
+}
+
+/// Byte ranges of the words in `text` matching `pattern`, where `*` stands
+/// for the rest of a word, e.g. `deploy*` for `deploying`.
+fn wildcard_word_matches(text: &str, pattern: &str) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut start = None;
+    // Each maximal run of nick characters is a word to test.
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        match (is_nick_char(c), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                if wildcard_match(pattern, &text[s..i]) {
+                    found.push((s, i));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// What a highlighted line should do besides being colored.
+#[derive(Default)]
+struct Alert {
+    bell: bool,
+    notify: bool,
+}
+
+/// How our nick is styled where others mention it: bold, in the theme's
+/// `own_nick_color` if it has one.
+fn mention_color(user_config: Option<&UserConfig>) -> String {
+    match user_config
+        .and_then(|c| c.theme.as_ref())
+        .and_then(|t| t.own_nick_color())
+    {
+        Some(color) => format!("\x1b[1m{}", fg_code(Some(color), "")),
+        None => MENTION_COLOR.to_string(),
+    }
+}
+
+/// Wraps each occurrence of `nick` in `text` with `color`, switching back to
+/// `restore` afterwards.
+fn color_nick(text: &str, nick: &str, color: &str, restore: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in word_matches(text, nick) {
+        out.push_str(&text[last..start]);
+        out.push_str(color);
+        out.push_str(&text[start..end]);
+        out.push_str(restore);
+        last = end;
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+/// Styles the body of someone else's message or action, `rendered` being
+/// `line` as `render` drew it: the user's nick is colored, and the whole body
+/// is highlighted when it mentions the nick or one of `words`. Returns what
+/// the highlight should set off, if there was one; a mention of the nick
+/// rings the bell and notifies when `notify` is set, a word does what its
+/// rule says. Anything else, our own echoes included, is left alone.
+fn mark_mentions(
+    line: &Message,
+    rendered: &str,
+    own_nick: &str,
+    mention_color: &str,
+    words: &[HighlightWord],
+    notify: bool,
+) -> (String, Option<Alert>) {
+    // `render` ends the `<nick>` or `* nick` header with a reset and a space.
+    const HEADER_END: &str = "\x1b[0m ";
+    let from_others = matches!(line.kind, MessageKind::Privmsg | MessageKind::Action)
+        && !line.outgoing
+        && line
+            .sender
+            .as_deref()
+            .is_some_and(|sender| !sender.eq_ignore_ascii_case(own_nick));
+    let i = match rendered.find(HEADER_END) {
+        Some(i) if from_others => i,
+        _ => return (rendered.to_string(), None),
+    };
+    let (header, body) = rendered.split_at(i + HEADER_END.len());
+    let mut alert: Option<Alert> = None;
+    if !word_matches(body, own_nick).is_empty() {
+        alert = Some(Alert { bell: true, notify });
+    }
+    for word in words
+        .iter()
+        .filter(|w| !word_matches(body, w.word()).is_empty())
+    {
+        let alert = alert.get_or_insert_with(Alert::default);
+        alert.bell |= word.bell();
+        alert.notify |= word.notify(notify);
+    }
+
+    if alert.is_some() {
+        let restore = format!("\x1b[22m{}", HIGHLIGHT_COLOR);
+        let body = color_nick(body, own_nick, mention_color, &restore);
+        (
+            format!("{}{}{}\x1b[0m", header, HIGHLIGHT_COLOR, body),
+            alert,
+        )
+    } else {
+        let body = color_nick(body, own_nick, mention_color, "\x1b[22;39m");
+        (format!("{}{}", header, body), None)
+    }
+}
+
+/// Where the next `http://` or `https://` link in `text` starts.
+fn find_url(text: &str) -> Option<usize> {
+    ["http://", "https://"]
+        .iter()
+        .filter_map(|scheme| {
+            text.match_indices(scheme)
+                .map(|(i, _)| i)
+                .find(|&i| !text[..i].ends_with(|c: char| c.is_alphanumeric()))
+        })
+        .min()
+}
+
+/// Underlines the links in a rendered line and, with `hyperlinks`, wraps
+/// each in an OSC 8 escape so terminals that support it make it clickable.
+/// Incoming text has had its escapes stripped before it gets here, so the
+/// only OSC 8 sequences on screen are the ones made here.
+fn linkify(line: &str, hyperlinks: bool) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = find_url(rest) {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let end = tail
+            .find(|c: char| c.is_whitespace() || c.is_control() || "<>\"".contains(c))
+            .unwrap_or(tail.len());
+        let mut url = &tail[..end];
+        // Trailing punctuation usually ends the sentence, not the link; a
+        // `)` stays when the link has its own `(`.
+        while let Some(last) = url.chars().last() {
+            let unbalanced = last == ')' && url.matches('(').count() < url.matches(')').count();
+            if !(".,;:!?'".contains(last) || unbalanced) {
+                break;
+            }
+            url = &url[..url.len() - 1];
+        }
+        if url.ends_with("//") {
+            // Just a scheme, nothing to link to.
+            out.push_str(url);
+        } else if hyperlinks {
+            out.push_str(&format!(
+                "\x1b]8;;{}\x1b\\\x1b[4m{}\x1b[24m\x1b]8;;\x1b\\",
+                url, url
+            ));
+        } else {
+            out.push_str(&format!("\x1b[4m{}\x1b[24m", url));
+        }
+        rest = &tail[url.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The OSC 8 sequence that opens the link `line` is still inside at its
+/// end, if any.
+fn open_link(line: &str) -> Option<&str> {
+    let start = line.rfind("\x1b]8;;")?;
+    let end = start + line[start..].find("\x1b\\")? + 2;
+    (end - start > 7).then(|| &line[start..end])
+}
+
+/// Limits how far back the view can scroll: the topmost visible line never
+/// goes above the first message, and an offset of 0 is always the live bottom.
+fn clamp_scroll(offset: usize, total: usize, height: usize) -> usize {
+    offset.min(total.saturating_sub(height))
+}
+
+/// Default palette for nicks when the theme doesn't set `nick_colors`.
+const DEFAULT_NICK_COLORS: &[(u8, u8, u8)] = &[
+    (230, 97, 97),
+    (230, 159, 84),
+    (222, 201, 92),
+    (140, 200, 95),
+    (88, 196, 143),
+    (82, 190, 200),
+    (95, 160, 230),
+    (128, 128, 235),
+    (170, 110, 230),
+    (215, 100, 200),
+    (235, 120, 160),
+    (180, 180, 180),
+];
+
+/// Gives every nick a stable color from a palette for the whole session.
+struct NickColors {
+    palette: Vec<Color>,
+    cache: HashMap<String, Color>,
+    own: Option<Color>, // for our own lines: `own_nick_color`, else the accent
+}
+
+impl NickColors {
+    fn new(user_config: &UserConfig) -> Self {
+        let palette: Vec<_> = user_config
+            .theme
+            .as_ref()
+            .and_then(|t| t.nick_colors.as_ref())
+            .map(|colors| colors.iter().filter_map(|c| parse_color(c)).collect())
+            .unwrap_or_default();
+        let palette = if palette.is_empty() {
+            DEFAULT_NICK_COLORS
+                .iter()
+                .map(|&(r, g, b)| Color::Rgb { r, g, b })
+                .collect()
+        } else {
+            palette
+        };
+        let theme = user_config.theme.as_ref();
+        let own = theme.and_then(|t| {
+            t.own_nick_color()
+                .or_else(|| t.accent.as_deref().and_then(parse_color))
+        });
+        NickColors {
+            palette,
+            cache: HashMap::new(),
+            own,
+        }
+    }
+
+    /// Keeps a nick's color after it changes to `new`.
+    fn rename(&mut self, old: &str, new: &str) {
+        let color = self.color(old);
+        self.cache.insert(new.to_ascii_lowercase(), color);
+    }
+
+    /// The color for `nick`, hashed case-insensitively (FNV-1a) into the palette.
+    fn color(&mut self, nick: &str) -> Color {
+        let key = nick.to_ascii_lowercase();
+        if let Some(color) = self.cache.get(&key) {
+            return *color;
+        }
+        let hash = key.bytes().fold(0x811c9dc5u32, |h, b| {
+            (h ^ b as u32).wrapping_mul(0x01000193)
+        });
+        let color = self.palette[hash as usize % self.palette.len()];
+        self.cache.insert(key, color);
+        color
+    }
+}
+
+/// A rendered line in the buffer, keeping its kind so it can be filtered.
+struct Line {
+    kind: MessageKind,
+    text: String,
+    indent: usize, // where wrapped lines start, under the text after `<nick> `
+}
+
+impl From<String> for Line {
+    fn from(text: String) -> Self {
+        Line {
+            kind: MessageKind::System,
+            text,
+            indent: 0,
+        }
+    }
+}
 
-pub fn parse_color(hex: &str) -> Option<Color> {
-    let hex = hex.trim_start_matches('#');
-    if hex.len() == 6 {
-        if let Ok(rgb) = u32::from_str_radix(hex, 16) {
-            let r = ((rgb >> 16) & 0xFF) as u8;
-            let g = ((rgb >> 8) & 0xFF) as u8;
-            let b = (rgb & 0xFF) as u8;
-            return Some(Color::Rgb { r, g, b });
+/// How far wrapped lines of a message are indented so they line up under
+/// its text rather than under the `<nick>` in front of it.
+fn hanging_indent(line: &Message) -> usize {
+    match line.kind {
+        MessageKind::Privmsg | MessageKind::Notice | MessageKind::Action => {
+            line.header().width() + 3
         }
+        MessageKind::Wallops => "*** WALLOPS from : ".len() + line.header().width(),
+        _ => 0,
     }
-    None
+}
+
+/// How many lines the buffer keeps; the oldest go first.
+const BUFFER_LIMIT: usize = 100;
+
+/// Adds a line to the buffer, dropping the oldest once it's full. Every
+/// line goes through here so nothing grows the buffer past the limit.
+fn push_line(messages: &mut VecDeque<Line>, line: Line) {
+    while messages.len() >= BUFFER_LIMIT {
+        messages.pop_front();
+    }
+    messages.push_back(line);
+}
+
+/// Whether a line is currently filtered out of the display.
+fn hides(kind: MessageKind, hide_joins: bool) -> bool {
+    hide_joins
+        && matches!(
+            kind,
+            MessageKind::Join | MessageKind::Part | MessageKind::Quit
+        )
+}
+
+/// Styles a line from the IRC task for display: nicks in their palette
+/// color (ours in its own), notices and joins/parts/quits in the muted
+/// color. With `strip`, the colors and formatting codes others put in their
+/// messages are removed.
+fn render(
+    line: &Message,
+    nick_colors: &mut NickColors,
+    muted: Option<Color>,
+    strip: bool,
+) -> String {
+    let text = if strip && !line.outgoing {
+        strip_formatting(&line.text)
+    } else {
+        line.text.clone()
+    };
+    let mut nick_color = || {
+        let color = match line.outgoing {
+            true => nick_colors.own,
+            false => Some(nick_colors.color(line.sender.as_deref().unwrap_or(""))),
+        };
+        fg_code(color, "38;2;128;0;128") // Default purple
+    };
+    match line.kind {
+        MessageKind::Privmsg => format!(
+            "\x1b[1m\x1b[{}m<{}>\x1b[0m {}",
+            nick_color(),
+            line.header(),
+            text
+        ),
+        MessageKind::Action => format!(
+            "\x1b[1m\x1b[{}m* {}\x1b[0m {}",
+            nick_color(),
+            line.header(),
+            text
+        ),
+        MessageKind::Notice => format!(
+            "\x1b[{}m-{}-\x1b[0m {}",
+            fg_code(muted, DEFAULT_MUTED),
+            line.header(),
+            text
+        ),
+        MessageKind::Join | MessageKind::Part | MessageKind::Quit | MessageKind::Nick => {
+            format!("\x1b[{}m{}\x1b[0m", fg_code(muted, DEFAULT_MUTED), line)
+        }
+        MessageKind::Wallops => format!(
+            "{}*** WALLOPS from {}:\x1b[0m {}",
+            WALLOPS_COLOR,
+            line.header(),
+            text
+        ),
+        MessageKind::System | MessageKind::Error | MessageKind::Json => line.text.clone(),
+    }
+}
+
+/// One visible character of a line to be wrapped, with any escape sequences
+/// just before it. The sequences take no room on screen.
+struct Cell {
+    escapes: String,
+    c: char,
+    width: usize,
+}
+
+/// Moves `line` onto `lines` and starts the next one. A link broken across
+/// the two is closed at the end of this line and reopened on the next, so
+/// it doesn't spill into whatever is drawn after it.
+fn end_line(lines: &mut Vec<String>, line: &mut String) {
+    let link = open_link(line).map(str::to_string);
+    if link.is_some() {
+        line.push_str("\x1b]8;;\x1b\\");
+    }
+    lines.push(std::mem::take(line));
+    line.extend(link);
+}
+
+/// Breaks `text` into lines of at most `width` columns, at spaces where it
+/// can; only a word wider than a whole line is split mid-word. Lines after
+/// the first get `indent` fewer columns, for a hanging indent. ANSI escape
+/// sequences are carried along without counting towards the width.
+fn wrap_text(text: &str, width: usize, indent: usize) -> Vec<String> {
+    let rest_width = width.saturating_sub(indent).max(1);
+    let mut width = width.max(1);
+    let mut cells = Vec::new();
+    let mut escapes = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            escapes.push(c);
+            if chars.peek() == Some(&']') {
+                // OSC (e.g. a hyperlink) runs to BEL or `ESC \`.
+                while let Some(c) = chars.next() {
+                    escapes.push(c);
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' {
+                        escapes.extend(chars.next_if_eq(&'\\'));
+                        break;
+                    }
+                }
+            } else {
+                // CSI sequences end at the first alphabetic character (e.g. 'm').
+                for c in chars.by_ref() {
+                    escapes.push(c);
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+        } else {
+            cells.push(Cell {
+                escapes: std::mem::take(&mut escapes),
+                c,
+                width: c.width().unwrap_or(0),
+            });
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut used = 0;
+    let mut i = 0;
+    while i < cells.len() {
+        if cells[i].c == ' ' {
+            if used + 1 > width {
+                // The break goes here; the space itself isn't needed.
+                end_line(&mut lines, &mut line);
+                width = rest_width;
+                line.push_str(&cells[i].escapes);
+                used = 0;
+            } else {
+                line.push_str(&cells[i].escapes);
+                line.push(' ');
+                used += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        let end = cells[i..]
+            .iter()
+            .position(|cell| cell.c == ' ')
+            .map_or(cells.len(), |n| i + n);
+        let word_width: usize = cells[i..end].iter().map(|cell| cell.width).sum();
+        if used > 0 && used + word_width > width && word_width <= width {
+            end_line(&mut lines, &mut line);
+            width = rest_width;
+            used = 0;
+        }
+        for cell in &cells[i..end] {
+            if used > 0 && used + cell.width > width {
+                end_line(&mut lines, &mut line);
+                width = rest_width;
+                used = 0;
+            }
+            line.push_str(&cell.escapes);
+            line.push(cell.c);
+            used += cell.width;
+        }
+        i = end;
+    }
+    line.push_str(&escapes);
+    match lines.last_mut() {
+        // Only escapes left over: keep them on the last line rather than a blank one.
+        Some(last) if used == 0 => last.push_str(&line),
+        _ if !line.is_empty() => lines.push(line),
+        _ => {}
+    }
+    lines
+}
+
+/// Cuts `text` down to at most `width` terminal columns, ending with an
+/// ellipsis when anything was dropped.
+fn truncate_to_width(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width {
+            while used + 1 > width {
+                match out.pop() {
+                    Some(last) => used -= last.width().unwrap_or(0),
+                    None => return out,
+                }
+            }
+            out.push('…');
+            return out;
+        }
+        out.push(c);
+        used += w;
+    }
+    out
+}
+
+/// The status line above the prompt: server, channel, nick, away and lag.
+fn status_text(status: &Status) -> String {
+    let Some(server) = &status.server else {
+        return match &status.connecting {
+            Some((server, since)) => {
+                let frame = (since.elapsed().as_millis() / 150) as usize % SPINNER.len();
+                format!("Connecting to {} {}", server, SPINNER[frame])
+            }
+            None => "Not connected".to_string(),
+        };
+    };
+    let mut parts = vec![server.clone()];
+    parts.extend(status.channel.clone());
+    parts.extend(status.nick.clone());
+    if status.away {
+        parts.push("away".to_string());
+    }
+    if let Some(lag) = status.lag {
+        parts.push(format!("lag {}ms", lag.as_millis()));
+    }
+    parts.join(" │ ")
+}
+
+/// Message area size for a terminal of `cols` x `rows`: the width left after
+/// the indent lines are drawn at, and every row except the header, the topic
+/// line, the status line and the prompt.
+fn layout(cols: u16, rows: u16, left_padding: usize) -> (usize, usize) {
+    let max_width = (cols as usize).saturating_sub(left_padding).max(20);
+    let max_height = (rows as usize).saturating_sub(4).max(1);
+    (max_width, max_height)
+}
+
+/// Reads `/connect <server|profile> [port] [nick] [tls] [password]`. Anything
+/// left out comes from the named profile, then from `[irc]`; `None` means
+/// there's no server to connect to at all.
+pub(crate) fn parse_connect(arg: &str, irc: Option<&IrcConfig>) -> Option<InputCommand> {
+    let mut args = arg.split_whitespace();
+    let server = args.next().unwrap_or("").to_string();
+    // `/connect <profile>` picks a named network from the config.
+    let profile = irc.and_then(|i| i.profiles.as_ref()?.get(&server));
+    let server = profile.and_then(|p| p.server.clone()).unwrap_or(server);
+    // A bare `/connect` goes to the configured server.
+    let server = match irc.and_then(|i| i.server.clone()) {
+        Some(default) if server.is_empty() => default,
+        _ => server,
+    };
+    if server.is_empty() {
+        return None;
+    }
+    let (server, inline_port) = split_port(&server);
+
+    // Arguments typed after the server win over the config.
+    let port = args
+        .next()
+        .and_then(|p| p.parse().ok())
+        .or(inline_port)
+        .or_else(|| profile?.port)
+        .or_else(|| irc?.port);
+    let nick = args
+        .next()
+        .map(str::to_string)
+        .or_else(|| profile?.nick.clone())
+        .or_else(|| irc?.nick.clone())
+        .unwrap_or_else(|| "meow".to_string());
+    let tls = args
+        .next()
+        .and_then(|t| t.parse().ok())
+        .or_else(|| profile?.tls)
+        .or_else(|| irc?.tls)
+        .unwrap_or(true);
+    let password = args
+        .next()
+        .map(str::to_string)
+        .or_else(|| profile?.server_password.clone())
+        .or_else(|| irc?.server_password.clone());
+    Some(InputCommand::Connect {
+        server: server.to_string(),
+        port: port.unwrap_or(default_port(tls)),
+        nick,
+        tls,
+        password,
+    })
+}
+
+/// `/connect` as typed, but with `********` for a server password so it's
+/// never echoed back to the screen.
+fn connect_echo(arg: &str) -> String {
+    let mut args: Vec<&str> = arg.split_whitespace().collect();
+    if let Some(password) = args.get_mut(4) {
+        *password = "********";
+    }
+    ["/connect"]
+        .into_iter()
+        .chain(args)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Splits a port written onto the server, as in `irc.example.net:6697` or
+/// `[2001:db8::1]:6697`. A bare IPv6 address like `::1` is left whole.
+fn split_port(server: &str) -> (&str, Option<u16>) {
+    let split = match server.strip_prefix('[') {
+        Some(rest) => rest
+            .split_once("]:")
+            .map(|(addr, port)| (&server[..addr.len() + 2], port)),
+        None => server
+            .split_once(':')
+            .filter(|(_, port)| !port.contains(':')),
+    };
+    match split.and_then(|(host, port)| Some((host, port.parse().ok()?))) {
+        Some((host, port)) => (host, Some(port)),
+        None => (server, None),
+    }
+}
+
+/// Splits an optional leading channel off a command argument, so operator
+/// commands can be written as `/op alice` or `/op #chan alice`.
+fn split_channel(arg: &str) -> (Option<String>, &str) {
+    let arg = arg.trim();
+    match arg.split_once(' ') {
+        Some((first, rest)) if first.starts_with(['#', '&', '+', '!']) => {
+            (Some(first.to_string()), rest.trim())
+        }
+        _ if arg.starts_with(['#', '&', '+', '!']) && !arg.contains(' ') => {
+            (Some(arg.to_string()), "")
+        }
+        _ => (None, arg),
+    }
+}
+
+/// What a line of input asks for, whether typed at the prompt or read from
+/// stdin in headless mode.
+pub(crate) enum Parsed {
+    /// Commands for the IRC task, in order, and what to echo as `You: …`
+    /// (with any password masked).
+    Send(Vec<InputCommand>, Option<String>),
+    /// A command only the interactive client carries out, like `/clear`.
+    Local {
+        cmd: String,
+        arg: String,
+        line: String,
+    },
+    /// A usage hint or error to show instead, then the line as typed.
+    Invalid(String, Option<String>),
+}
+
+/// Keeps a message that starts with `:` intact once it goes out as the last
+/// IRC parameter.
+fn prefix_message(input: &str) -> String {
+    if input == ":)" {
+        return "::)".to_string();
+    }
+
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if words.len() == 1 && input.starts_with(':') {
+        format!(":{}", input)
+    } else {
+        input.to_string()
+    }
+}
+
+/// Turns a line of input into commands for the IRC task. Aliases are
+/// expanded first; anything not starting with `/` goes to the current target.
+pub(crate) fn parse_input(input: &str, config: Option<&UserConfig>) -> Parsed {
+    // A leading `/alias` becomes its definition before anything else sees it.
+    let line = match config
+        .and_then(|c| c.aliases.as_ref())
+        .map(|a| a.expand(input))
+    {
+        Some(Some(expanded)) => expanded,
+        Some(None) => {
+            let name = input.split(' ').next().unwrap_or("");
+            return Parsed::Invalid(format!("Alias loop: {} expands into itself", name), None);
+        }
+        None => input.to_string(),
+    };
+
+    if !line.starts_with('/') {
+        if line.trim().is_empty() {
+            return Parsed::Send(Vec::new(), None);
+        }
+        let message = prefix_message(&line);
+        return Parsed::Send(
+            vec![InputCommand::SendPlainMessage(message.clone())],
+            Some(message),
+        );
+    }
+
+    let mut parts = line.trim().splitn(2, ' ');
+    let cmd = parts.next().unwrap_or("");
+    let arg = parts.next().unwrap_or("");
+    // Most commands are echoed just as they were typed.
+    let send = |command| Parsed::Send(vec![command], Some(line.clone()));
+    let usage = |usage: &str| Parsed::Invalid(format!("Usage: {}", usage), Some(line.clone()));
+    let optional = |arg: &str| Some(arg.trim().to_string()).filter(|a| !a.is_empty());
+
+    match cmd {
+        "/connect" => match parse_connect(arg, config.and_then(|c| c.irc.as_ref())) {
+            Some(connect) => Parsed::Send(vec![connect], Some(connect_echo(arg))),
+            None => Parsed::Invalid(
+                format!("Usage: {}, or set server under [irc]", CONNECT_USAGE),
+                None,
+            ),
+        },
+        "/join" => {
+            let mut args = arg.split_whitespace();
+            let channels = args.next().unwrap_or("").to_string();
+            let keys = args.next().map(str::to_string);
+            // Channel keys are passwords; keep them off the screen.
+            let shown = match keys.as_deref().and_then(|k| line.rsplit_once(k)) {
+                Some((before, after)) => format!("{}********{}", before, after),
+                None => line.clone(),
+            };
+            Parsed::Send(vec![InputCommand::JoinChannel(channels, keys)], Some(shown))
+        }
+        "/part" => send(InputCommand::PartChannel(arg.to_string())),
+        // Echoed as `>> line` once it's sent.
+        "/raw" if arg.trim().is_empty() => Parsed::Invalid("Usage: /raw <line>".to_string(), None),
+        "/raw" => Parsed::Send(vec![InputCommand::Raw(arg.trim().to_string())], None),
+        "/clear" | "/filter" | "/colors" | "/reload" | "/help" => Parsed::Local {
+            cmd: cmd.to_string(),
+            arg: arg.to_string(),
+            line: line.clone(),
+        },
+        "/reconnect" => send(InputCommand::Reconnect),
+        "/ignore" => send(InputCommand::Ignore(
+            arg.split_whitespace().next().map(str::to_string),
+        )),
+        "/unignore" => match arg.split_whitespace().next() {
+            Some(mask) => send(InputCommand::Unignore(mask.to_string())),
+            None => usage("/unignore <nick|mask>"),
+        },
+        "/motd" => send(InputCommand::Motd),
+        "/ping" => send(InputCommand::Ping),
+        "/whois" => match optional(arg) {
+            Some(nick) => send(InputCommand::Whois(nick)),
+            None => usage("/whois <nick>"),
+        },
+        "/list" => send(InputCommand::ListChannels(optional(arg))),
+        "/names" => send(InputCommand::Names(optional(arg))),
+        "/msg" => match arg.split_once(' ') {
+            Some((target, message)) => {
+                let message = prefix_message(message);
+                let shown = format!("/msg {} {}", target, message);
+                let target = target.to_string();
+                Parsed::Send(
+                    vec![InputCommand::SendMessage { target, message }],
+                    Some(shown),
+                )
+            }
+            None => usage("/msg <target>[,target...] <message>"),
+        },
+        "/query" => {
+            let mut query_parts = arg.trim().splitn(2, ' ');
+            let Some(nick) = query_parts.next().filter(|n| !n.is_empty()) else {
+                return usage("/query <nick> [message]");
+            };
+            let mut commands = vec![InputCommand::OpenQuery(nick.to_string())];
+            if let Some(message) = query_parts.next().and_then(optional) {
+                commands.push(InputCommand::SendMessage {
+                    target: nick.to_string(),
+                    message: prefix_message(&message),
+                });
+            }
+            Parsed::Send(commands, Some(line.clone()))
+        }
+        "/mode" => {
+            let mut args = arg.split_whitespace();
+            match args.next() {
+                Some(target) => send(InputCommand::Mode {
+                    target: target.to_string(),
+                    args: args.map(str::to_string).collect(),
+                }),
+                None => usage("/mode <target> [modes] [args]"),
+            }
+        }
+        "/kick" | "/ban" | "/op" | "/deop" | "/voice" | "/devoice" => {
+            let (channel, rest) = split_channel(arg);
+            let (target, reason) = match rest.split_once(' ') {
+                Some((target, reason)) => (target.to_string(), Some(reason.trim().to_string())),
+                None => (rest.to_string(), None),
+            };
+            if target.is_empty() {
+                return usage(&format!("{} [#channel] <nick>", cmd));
+            }
+            send(match cmd {
+                "/kick" => InputCommand::Kick {
+                    channel,
+                    nick: target,
+                    reason,
+                },
+                "/ban" => InputCommand::Ban {
+                    channel,
+                    mask: target,
+                },
+                "/op" => InputCommand::Op {
+                    channel,
+                    nick: target,
+                },
+                "/deop" => InputCommand::Deop {
+                    channel,
+                    nick: target,
+                },
+                "/voice" => InputCommand::Voice {
+                    channel,
+                    nick: target,
+                },
+                _ => InputCommand::Devoice {
+                    channel,
+                    nick: target,
+                },
+            })
+        }
+        "/away" => send(InputCommand::SetAway(optional(arg))),
+        "/notice" => match arg.split_once(' ') {
+            Some((target, message)) if !message.trim().is_empty() => {
+                send(InputCommand::SendNotice {
+                    target: target.to_string(),
+                    message: message.to_string(),
+                })
+            }
+            _ => usage("/notice <target> <message>"),
+        },
+        "/disconnect" => send(InputCommand::Disconnect(optional(arg))),
+        "/quit" => send(InputCommand::Quit(optional(arg))),
+        _ => Parsed::Invalid(format!("Unknown command: {}", cmd), Some(line.clone())),
+    }
+}
+
+/// Hands the terminal back to the shell and stops the process (Ctrl+Z).
+/// Raw mode swallows the terminal's own SIGTSTP, so we raise it ourselves.
+#[cfg(unix)]
+fn suspend(stdout: &mut std::io::Stdout) -> anyhow::Result<()> {
+    execute!(
+        stdout,
+        ResetColor,
+        cursor::Show,
+        DisableBracketedPaste,
+        DisableMouseCapture,
+        LeaveAlternateScreen
+    )?;
+    disable_raw_mode()?;
+    signal_hook::low_level::raise(signal_hook::consts::SIGTSTP)?;
+    Ok(())
+}
+
+/// Re-enters raw mode and the alternate screen after a SIGCONT (or whatever
+/// else left the terminal in a mess, for Ctrl+L), so the next frame repaints
+/// the visible window from the buffer instead of a blank or garbled screen.
+fn resume(
+    stdout: &mut std::io::Stdout,
+    bg_color: Option<Color>,
+    mouse_scroll: bool,
+) -> anyhow::Result<()> {
+    enable_raw_mode()?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        cursor::Hide,
+        EnableBracketedPaste
+    )?;
+    let _ = execute!(stdout, EnableFocusChange);
+    if mouse_scroll {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+    if let Some(bg) = bg_color {
+        execute!(stdout, SetBackgroundColor(bg))?;
+    }
+    execute!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
+    Ok(())
 }
 
 pub async fn run_ui(
     input_tx: Sender<InputCommand>,
-    mut irc_rx: Receiver<String>,
+    mut irc_rx: Receiver<Message>,
     accent_color_hex: Option<String>,
+    status: SharedStatus,
 ) -> anyhow::Result<()> {
-    let config = UserConfig::load();
-    let icons_enabled = config
-        .as_ref()
-        .and_then(|cfg| cfg.theme.as_ref()?.icons)
-        .unwrap_or(false);
+    let mut config = UserConfig::load();
+    let ascii = config.clone().unwrap_or_default().ascii();
+    // Decorations go through here so ASCII mode can swap their glyphs.
+    let deco = |text: &str| {
+        if ascii {
+            asciify(text)
+        } else {
+            text.to_string()
+        }
+    };
+    let icons_enabled = !ascii
+        && config
+            .as_ref()
+            .and_then(|cfg| cfg.theme.as_ref()?.icons)
+            .unwrap_or(false);
 
     let theme = config.as_ref().and_then(|cfg| cfg.theme.as_ref());
-    let fg_color = theme
+    let mut fg_color = theme
         .and_then(|t| t.foreground.as_deref())
         .and_then(parse_color);
-    let bg_color = theme
+    let mut bg_color = theme
         .and_then(|t| t.background.as_deref())
         .and_then(parse_color);
     let accent_color = accent_color_hex.and_then(|hex| parse_color(&hex));
-    let muted_color = theme.and_then(|t| t.muted.as_deref()).and_then(parse_color);
+    let mut muted_color = theme.and_then(|t| t.muted.as_deref()).and_then(parse_color);
+    let mut prompt_text = deco(theme.map_or(DEFAULT_PROMPT, |t| t.prompt()));
 
     enable_raw_mode()?;
     let mut stdout = stdout();
     execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+    // Pastes arrive as one event instead of keystrokes; unsupported on legacy Windows consoles.
+    let _ = execute!(stdout, EnableBracketedPaste);
+    // Focus reports tell us when a highlight deserves a desktop notification.
+    let _ = execute!(stdout, EnableFocusChange);
+    let mut focused = true;
+    // Idle this long and we're marked away until the next keypress.
+    let mut auto_away_after = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.auto_away_minutes)
+        .filter(|m| *m > 0)
+        .map(|m| Duration::from_secs(m * 60));
+    let mut last_key = Instant::now();
+    let mut auto_away = false;
+    let mut notifier = Notifier::default();
+    // Capturing the mouse gets wheel scrolling but takes over text selection, so it's opt-in.
+    let mut scroll_step = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.scroll_step)
+        .unwrap_or_default();
+    let mut mouse_scroll = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.mouse_scroll)
+        .unwrap_or(false);
+    if mouse_scroll {
+        execute!(stdout, EnableMouseCapture)?;
+    }
 
     if let Some(bg) = bg_color {
         execute!(stdout, SetBackgroundColor(bg))?;
     }
 
     let mut input = String::new();
-    let mut messages: VecDeque<Vec<String>> = VecDeque::with_capacity(100);
+    let mut cursor_pos: usize = 0; // byte offset into `input`
+                                   // Unwrapped lines; wrapped to the current terminal width when drawn.
+    let mut messages: VecDeque<Line> = VecDeque::with_capacity(BUFFER_LIMIT);
+    for problem in theme.map(|t| t.problems()).unwrap_or_default() {
+        push_line(&mut messages, problem.into());
+    }
+    let ui_config = config.as_ref().and_then(|c| c.ui.as_ref());
+    for problem in ui_config.map(|u| u.problems()).unwrap_or_default() {
+        push_line(&mut messages, problem.into());
+    }
+    let mut timestamp_format = ui_config.and_then(|u| u.timestamp_format().map(str::to_string));
+    let mut timestamps_utc = ui_config.is_some_and(|u| u.timestamps_utc());
     let mut scroll_offset: usize = 0;
-    let mut input_history: Vec<String> = Vec::new();
+    // Lines that arrived while the view was held still for typing.
+    let mut paused_lines: usize = 0;
+    let history_size = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.history_size)
+        .unwrap_or(history::DEFAULT_SIZE);
+    let mut input_history: Vec<String> = history::load(history_size);
     let mut input_history_index: Option<usize> = None;
+    let mut search: Option<HistorySearch> = None; // Ctrl+R in progress
+    let mut own_nick = config
+        .as_ref()
+        .and_then(|c| c.irc.as_ref()?.nick.clone())
+        .unwrap_or_else(|| "meow".to_string());
+    let mut pause_while_typing = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.pause_scroll_while_typing)
+        .unwrap_or(false);
+    let mut hanging_indents = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.hanging_indent)
+        .unwrap_or(true);
+    let mut hyperlinks = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.hyperlinks)
+        .unwrap_or(true);
+    let mut strip_colors = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.strip_formatting)
+        .unwrap_or(false);
+    let mut hide_joins = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.hide_joins)
+        .unwrap_or(false);
+    let mut join_pastes = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.join_pastes)
+        .unwrap_or(false);
+    let mut paste_confirm_lines = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.paste_confirm_lines)
+        .unwrap_or(DEFAULT_PASTE_CONFIRM_LINES);
+    let mut pending_paste: Option<Vec<String>> = None; // waiting for y/n
+    let mut pasted_command: Option<String> = None; // the input line, as pasted, when it starts with `/`
+    let mut emoji_aliases = alias_names(config.as_ref());
+    let mut nick_colors = NickColors::new(&config.clone().unwrap_or_default());
+    let mut mention_color = mention_color(config.as_ref());
+    let mut highlight_words: Vec<HighlightWord> = config
+        .as_ref()
+        .and_then(|c| c.highlight.as_ref()?.words.clone())
+        .unwrap_or_default();
+    let mut notifications = config
+        .as_ref()
+        .and_then(|c| c.highlight.as_ref()?.notifications)
+        .unwrap_or(false);
 
-    let max_width = 80;
+    let (cols, rows) = terminal::size().unwrap_or((80, 24));
     let left_padding = 2;
-    let max_height = 20;
+    let (mut max_width, mut max_height) = layout(cols, rows, left_padding);
 
-    fn format_message(msg: &str, max_width: usize, left_padding: usize) -> Vec<String> {
+    fn format_message(
+        msg: &str,
+        max_width: usize,
+        left_padding: usize,
+        indent: usize,
+    ) -> Vec<String> {
         let available_width = max_width.saturating_sub(left_padding);
-        let mut lines = Vec::new();
-        let mut current_line = String::new();
-        let mut current_display_len = 0;
-        let mut in_ansi_sequence = false;
-
-        for c in msg.chars() {
-            if c == '\x1b' {
-                in_ansi_sequence = true;
-                current_line.push(c);
-            } else if in_ansi_sequence {
-                current_line.push(c);
-                if c.is_ascii_alphabetic() {
-                    // End of a simple ANSI sequence (e.g., 'm')
-                    in_ansi_sequence = false;
-                }
-            } else {
-                // Regular character
-                let char_display_width = 1; // Simplified: assume each character takes 1 display width
-                if current_display_len + char_display_width > available_width {
-                    let padded_line =
-                        format!("{:width$}{}", "", current_line, width = left_padding);
-                    lines.push(format!("{:<width$}", padded_line, width = max_width));
-                    current_line.clear();
-                    current_display_len = 0;
-                }
-                current_line.push(c);
-                current_display_len += char_display_width;
-            }
-        }
-        if !current_line.is_empty() {
-            let padded_line = format!("{:width$}{}", "", current_line, width = left_padding);
-            lines.push(format!("{:<width$}", padded_line, width = max_width));
-        }
-        lines
+        // On a narrow terminal an indent would leave too little room for the text.
+        let indent = if indent * 2 > available_width {
+            0
+        } else {
+            indent
+        };
+        wrap_text(msg, available_width, indent)
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let pad = if i == 0 {
+                    left_padding
+                } else {
+                    left_padding + indent
+                };
+                let padded_line = format!("{:width$}{}", "", line, width = pad);
+                format!("{:<width$}", padded_line, width = max_width)
+            })
+            .collect()
     }
 
-    fn prefix_message(input: &str) -> String {
-        if input == ":)" {
-            return "::)".to_string();
-        }
+    // Experienced users can go straight to the client.
+    let skip_welcome = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.skip_welcome)
+        .unwrap_or(false);
+    if !skip_welcome {
+        execute!(stdout, Clear(ClearType::All))?;
+        let icon = if icons_enabled { "󰄛 " } else { "" };
+        let lines = [
+            "╭────────────────────────────────────────────────────────────╮",
+            &format!(
+                "│{:14}\x1b[1m{}Welcome to meow IRC Client\x1b[0m{:width$}│",
+                "",
+                icon,
+                "",
+                width = 60 - 14 - 26 - icon.width()
+            ),
+            "├────────────────────────────────────────────────────────────┤",
+            &format!("│  \x1b[3m{:<58}\x1b[0m│", "Available Commands:"),
+            "│                                                            │",
+            &format!("│  \x1b[1m{:<58}\x1b[0m│", CONNECT_USAGE),
+            &format!("│  \x1b[1m{:<58}\x1b[0m│", "/join <#channel>"),
+            &format!("│  \x1b[1m{:<58}\x1b[0m│", "/part <#channel>"),
+            &format!("│  \x1b[1m{:<58}\x1b[0m│", "/msg <target> <message>"),
+            &format!("│  \x1b[1m{:<58}\x1b[0m│", "/quit"),
+            "╰────────────────────────────────────────────────────────────╯",
+            "",
+            "Press \x1b[1mEnter\x1b[0m to continue...",
+        ];
 
-        let words: Vec<&str> = input.split_whitespace().collect();
-        if words.len() == 1 && input.starts_with(':') {
-            format!(":{}", input)
+        if let Some(color) = accent_color {
+            execute!(stdout, SetForegroundColor(color))?;
         } else {
-            input.to_string()
+            execute!(stdout, SetForegroundColor(Color::Cyan))?;
         }
-    }
 
-    execute!(stdout, Clear(ClearType::All))?;
-    let icon = if icons_enabled { "󰄛 " } else { "" };
-    let lines = [
-        "╭────────────────────────────────────────────────────────────╮",
-        &format!(
-            "│              \x1b[1m{}Welcome to meow IRC Client\x1b[0m              │",
-            icon
-        ),
-        "├────────────────────────────────────────────────────────────┤",
-        "│  \x1b[3mAvailable Commands:\x1b[0m                                  │",
-        "│                                                            │",
-        "│  \x1b[1m/connect <server> <port> <nick> <tls>\x1b[0m                 │",
-        "│  \x1b[1m/join <#channel>\x1b[0m                                │",
-        "│  \x1b[1m/part <#channel>\x1b[0m                                │",
-        "│  \x1b[1m/msg <target> <message>\x1b[0m                         │",
-        "│  \x1b[1m/quit\x1b[0m                                           │",
-        "╰────────────────────────────────────────────────────────────╯",
-        "",
-        "Press \x1b[1mEnter\x1b[0m to continue...",
-    ];
-
-    if let Some(color) = accent_color {
-        execute!(stdout, SetForegroundColor(color))?;
-    } else {
-        execute!(stdout, SetForegroundColor(Color::Cyan))?;
-    }
-
-    let mut y = 2;
-    for line in lines.iter() {
-        for wrapped_line in format_message(line, max_width, 0) {
-            execute!(stdout, cursor::MoveTo(left_padding as u16, y))?;
-            writeln!(stdout, "{}", wrapped_line)?;
-            y += 1;
+        let mut y = 2;
+        for line in lines.iter() {
+            for wrapped_line in format_message(&deco(line), max_width, 0, 0) {
+                execute!(stdout, cursor::MoveTo(left_padding as u16, y))?;
+                writeln!(stdout, "{}", wrapped_line)?;
+                y += 1;
+            }
         }
-    }
-    execute!(stdout, SetForegroundColor(Color::Reset))?;
-    stdout.flush()?;
+        execute!(stdout, SetForegroundColor(Color::Reset))?;
+        stdout.flush()?;
 
-    loop {
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                if key.code == KeyCode::Enter {
-                    break;
+        loop {
+            if event::poll(Duration::from_millis(100))? {
+                if let Event::Key(key) = event::read()? {
+                    if key.code == KeyCode::Enter {
+                        break;
+                    }
                 }
             }
         }
@@ -169,264 +1497,745 @@ pub async fn run_ui(
     execute!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
     stdout.flush()?;
 
+    #[cfg(unix)]
+    let mut sigcont = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::from_raw(
+        signal_hook::consts::SIGCONT,
+    ))?;
+
+    let mut watcher = config
+        .as_ref()
+        .and_then(|c| c.ui.as_ref()?.watch_config)
+        .unwrap_or(false)
+        .then(ConfigWatcher::new);
+    let mut reload = false;
+    // Whether the next frame has to be drawn, and the topic and status line it last showed.
+    let mut dirty = true;
+    let mut shown: (Option<String>, Option<(bool, String)>) = (None, None);
     let mut running = true;
     while running {
-        while let Ok(msg) = irc_rx.try_recv() {
-            if messages.len() == 100 {
-                messages.pop_front();
+        // Coming back from a suspend: restore the terminal before repainting.
+        #[cfg(unix)]
+        if sigcont.recv().now_or_never().is_some() {
+            resume(&mut stdout, bg_color, mouse_scroll)?;
+            dirty = true;
+        }
+        // The IRC task is done, say after a SIGINT made it quit: so are we.
+        if input_tx.is_closed() {
+            break;
+        }
+
+        // `/reload`, or the file changed under `watch_config`.
+        if watcher.as_mut().is_some_and(|w| w.changed()) {
+            reload = true;
+        }
+        if std::mem::take(&mut reload) {
+            dirty = true;
+            match UserConfig::read() {
+                Ok(new_config) => {
+                    let new_config = new_config.unwrap_or_default();
+                    let changed = config
+                        .clone()
+                        .unwrap_or_default()
+                        .changed_sections(&new_config);
+
+                    let theme = new_config.theme.as_ref();
+                    fg_color = theme
+                        .and_then(|t| t.foreground.as_deref())
+                        .and_then(parse_color);
+                    bg_color = theme
+                        .and_then(|t| t.background.as_deref())
+                        .and_then(parse_color);
+                    muted_color = theme.and_then(|t| t.muted.as_deref()).and_then(parse_color);
+                    prompt_text = deco(theme.map_or(DEFAULT_PROMPT, |t| t.prompt()));
+                    pause_while_typing = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.pause_scroll_while_typing)
+                        .unwrap_or(false);
+                    hide_joins = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.hide_joins)
+                        .unwrap_or(false);
+                    scroll_step = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.scroll_step)
+                        .unwrap_or_default();
+                    let new_mouse_scroll = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.mouse_scroll)
+                        .unwrap_or(false);
+                    if new_mouse_scroll != mouse_scroll {
+                        mouse_scroll = new_mouse_scroll;
+                        if mouse_scroll {
+                            execute!(stdout, EnableMouseCapture)?;
+                        } else {
+                            execute!(stdout, DisableMouseCapture)?;
+                        }
+                    }
+                    hanging_indents = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.hanging_indent)
+                        .unwrap_or(true);
+                    strip_colors = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.strip_formatting)
+                        .unwrap_or(false);
+                    hyperlinks = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.hyperlinks)
+                        .unwrap_or(true);
+                    join_pastes = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.join_pastes)
+                        .unwrap_or(false);
+                    paste_confirm_lines = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.paste_confirm_lines)
+                        .unwrap_or(DEFAULT_PASTE_CONFIRM_LINES);
+                    mention_color = self::mention_color(Some(&new_config));
+                    highlight_words = new_config
+                        .highlight
+                        .as_ref()
+                        .and_then(|h| h.words.clone())
+                        .unwrap_or_default();
+                    notifications = new_config
+                        .highlight
+                        .as_ref()
+                        .and_then(|h| h.notifications)
+                        .unwrap_or(false);
+                    nick_colors = NickColors::new(&new_config);
+                    emoji_aliases = alias_names(Some(&new_config));
+                    auto_away_after = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.auto_away_minutes)
+                        .filter(|m| *m > 0)
+                        .map(|m| Duration::from_secs(m * 60));
+                    let watch = new_config
+                        .ui
+                        .as_ref()
+                        .and_then(|u| u.watch_config)
+                        .unwrap_or(false);
+                    if watch != watcher.is_some() {
+                        watcher = watch.then(ConfigWatcher::new);
+                    }
+
+                    input_tx
+                        .send(InputCommand::ReloadConfig(Box::new(new_config.clone())))
+                        .await?;
+                    config = Some(new_config);
+
+                    let report = if changed.is_empty() {
+                        "*** Config reloaded (no changes)".to_string()
+                    } else {
+                        format!("*** Config reloaded (changed: {})", changed.join(", "))
+                    };
+                    push_line(&mut messages, report.into());
+                    let theme = config.as_ref().and_then(|c| c.theme.as_ref());
+                    for problem in theme.map(|t| t.problems()).unwrap_or_default() {
+                        push_line(&mut messages, problem.into());
+                    }
+                    let ui_config = config.as_ref().and_then(|c| c.ui.as_ref());
+                    for problem in ui_config.map(|u| u.problems()).unwrap_or_default() {
+                        push_line(&mut messages, problem.into());
+                    }
+                    timestamp_format =
+                        ui_config.and_then(|u| u.timestamp_format().map(str::to_string));
+                    timestamps_utc = ui_config.is_some_and(|u| u.timestamps_utc());
+                }
+                Err(e) => {
+                    push_line(&mut messages, "Error reloading config:".to_string().into());
+                    for line in e.to_string().lines().filter(|l| !l.trim().is_empty()) {
+                        push_line(&mut messages, line.to_string().into());
+                    }
+                }
             }
-            messages.push_back(format_message(&msg, max_width, left_padding));
         }
 
-        if let Some(bg) = bg_color {
-            execute!(stdout, SetBackgroundColor(bg))?;
+        // Going idle: unless already away (say, by /away), mark us away.
+        if !auto_away && auto_away_after.is_some_and(|after| last_key.elapsed() >= after) {
+            let online = status.lock().is_ok_and(|s| s.server.is_some() && !s.away);
+            if online {
+                input_tx.send(InputCommand::AutoAway(true)).await?;
+                auto_away = true;
+            }
         }
-        execute!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
-        if let Some(color) = fg_color {
-            execute!(stdout, SetForegroundColor(color))?;
-        } else {
-            execute!(
-                stdout,
-                SetForegroundColor(Color::Blue),
-                SetAttribute(Attribute::Bold)
-            )?;
+
+        let lines = take_lines(&mut irc_rx);
+        let drained = lines.len();
+        dirty |= drained > 0;
+        let mut bell = false; // rung with the next frame, not on its own
+        for line in lines {
+            // Follow the nick the server settled on (e.g. after a 433 fallback).
+            if let Some(nick) = status.lock().ok().and_then(|s| s.nick.clone()) {
+                own_nick = nick;
+            }
+            if line.kind == MessageKind::Nick {
+                if let Some(old) = &line.sender {
+                    nick_colors.rename(old, &line.text);
+                }
+            }
+            let msg = render(&line, &mut nick_colors, muted_color, strip_colors);
+            let (msg, alert) = mark_mentions(
+                &line,
+                &msg,
+                &own_nick,
+                &mention_color,
+                &highlight_words,
+                notifications,
+            );
+            let msg = linkify(&msg, hyperlinks);
+            let mut indent = hanging_indent(&line);
+            // The time goes in front of everything, muted; wrapped lines still start under the text.
+            let msg = match &timestamp_format {
+                Some(format) => {
+                    let stamp = format_time(&line.timestamp, format, timestamps_utc);
+                    indent += stamp.width() + 1;
+                    let muted = fg_code(muted_color, DEFAULT_MUTED);
+                    format!("\x1b[{}m{}\x1b[0m {}", muted, stamp, msg)
+                }
+                None => msg,
+            };
+            if let Some(alert) = alert {
+                bell |= alert.bell;
+                if alert.notify && !focused {
+                    let sender = line.sender.as_deref().unwrap_or("someone");
+                    let title = match line.target.as_deref() {
+                        Some(target) if target != sender => format!("{} in {}", sender, target),
+                        _ => sender.to_string(),
+                    };
+                    notifier.notify(&title, &strip_formatting(&line.text));
+                }
+            }
+            let line_count = if hides(line.kind, hide_joins) {
+                0
+            } else {
+                let indent = if hanging_indents { indent } else { 0 };
+                format_message(&msg, max_width, left_padding, indent).len()
+            };
+            if pause_while_typing && !input.is_empty() {
+                // Keep what the user is reading in place while they compose.
+                scroll_offset += line_count;
+                paused_lines += line_count;
+            } else if scroll_offset > 0 {
+                // Scrolled back: new lines shouldn't drag the view down.
+                scroll_offset += line_count;
+            }
+            push_line(
+                &mut messages,
+                Line {
+                    kind: line.kind,
+                    text: msg,
+                    indent,
+                },
+            );
         }
-        writeln!(
-            stdout,
-            "{}╭─ meow IRC Client ── Type /help for commands. ESC to quit ─╮",
-            " ".repeat(left_padding)
-        )?;
-        execute!(stdout, SetForegroundColor(Color::Reset))?;
 
-        let flat_messages: Vec<String> = messages.iter().flat_map(|v| v.clone()).collect();
-        let start = if flat_messages.len() > max_height + scroll_offset {
-            flat_messages.len() - max_height - scroll_offset
-        } else {
-            0
-        };
-        let end = flat_messages.len().saturating_sub(scroll_offset);
+        // Messages are kept unwrapped and wrapped here at the current size, so
+        // a resized terminal simply re-wraps everything on the next frame.
+        let flat_messages: Vec<String> = messages
+            .iter()
+            .filter(|m| !hides(m.kind, hide_joins))
+            .flat_map(|m| {
+                let indent = if hanging_indents { m.indent } else { 0 };
+                format_message(&m.text, max_width, left_padding, indent)
+            })
+            .collect();
+        scroll_offset = clamp_scroll(scroll_offset, flat_messages.len(), max_height);
+        paused_lines = paused_lines.min(scroll_offset);
 
-        for (i, msg) in flat_messages.iter().take(end).skip(start).enumerate() {
-            execute!(stdout, cursor::MoveTo(0, (i + 2) as u16))?;
+        // The topic and status line can change without a new line arriving
+        // (a /ping reply, going away), so they're compared with the last frame.
+        let topic = status.lock().ok().and_then(|status| {
+            let channel = status.channel.as_ref()?;
+            Some(format!("{}: {}", channel, status.topics.get(channel)?))
+        });
+        let status_line = status
+            .lock()
+            .ok()
+            .map(|s| (s.server.is_some(), deco(&status_text(&s))));
+        // Nothing new to show: leave the screen alone rather than clearing and
+        // redrawing it, which flickers.
+        if dirty || (&topic, &status_line) != (&shown.0, &shown.1) {
+            // The whole frame is built up here and written in one go, so a slow
+            // link (say, over SSH) never shows a half-drawn screen.
+            let mut frame: Vec<u8> = Vec::new();
+            if bell {
+                frame.push(0x07);
+            }
             if let Some(bg) = bg_color {
-                execute!(
-                    stdout,
-                    SetBackgroundColor(bg),
-                    Clear(ClearType::CurrentLine)
+                queue!(frame, SetBackgroundColor(bg))?;
+            }
+            queue!(frame, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
+            if let Some(color) = fg_color {
+                queue!(frame, SetForegroundColor(color))?;
+            } else {
+                queue!(
+                    frame,
+                    SetForegroundColor(Color::Blue),
+                    SetAttribute(Attribute::Bold)
                 )?;
             }
-            execute!(stdout, cursor::MoveTo(left_padding as u16, (i + 2) as u16))?;
-            write!(stdout, "{}", msg)?;
-            writeln!(stdout)?;
-        }
+            writeln!(
+                frame,
+                "{}{}",
+                " ".repeat(left_padding),
+                deco("╭─ meow IRC Client ── Type /help for commands. ESC to quit ─╮")
+            )?;
+            queue!(frame, SetForegroundColor(Color::Reset))?;
 
-        execute!(stdout, cursor::MoveTo(0, (max_height + 2) as u16))?;
-        writeln!(stdout)?;
-        if let Some(bg) = bg_color {
-            execute!(stdout, SetBackgroundColor(bg))?;
-        }
-        if let Some(color) = muted_color {
-            execute!(stdout, SetForegroundColor(color))?;
-        } else {
-            execute!(
-                stdout,
-                SetForegroundColor(Color::Green),
-                SetAttribute(Attribute::Bold)
+            // Keep the active channel's topic pinned just under the header.
+            if let Some(topic) = &topic {
+                if let Some(color) = muted_color {
+                    queue!(frame, SetForegroundColor(color))?;
+                }
+                queue!(frame, cursor::MoveTo(left_padding as u16, 1))?;
+                write!(
+                    frame,
+                    "{}",
+                    deco(&truncate_to_width(
+                        topic,
+                        max_width.saturating_sub(left_padding)
+                    ))
+                )?;
+                queue!(frame, SetForegroundColor(Color::Reset))?;
+            }
+
+            let start = if flat_messages.len() > max_height + scroll_offset {
+                flat_messages.len() - max_height - scroll_offset
+            } else {
+                0
+            };
+            let end = flat_messages.len().saturating_sub(scroll_offset);
+
+            for (i, msg) in flat_messages.iter().take(end).skip(start).enumerate() {
+                queue!(frame, cursor::MoveTo(0, (i + 2) as u16))?;
+                if let Some(bg) = bg_color {
+                    queue!(frame, SetBackgroundColor(bg), Clear(ClearType::CurrentLine))?;
+                }
+                queue!(frame, cursor::MoveTo(left_padding as u16, (i + 2) as u16))?;
+                write!(frame, "{}", msg)?;
+                writeln!(frame)?;
+            }
+
+            // Status line between the messages and the prompt.
+            queue!(
+                frame,
+                cursor::MoveTo(left_padding as u16, (max_height + 2) as u16)
             )?;
-        }
-        for line in format_message(&format!("❯ {}", input), max_width, left_padding) {
+            if let Some((connected, status_line)) = &status_line {
+                match (*connected, accent_color, muted_color) {
+                    (true, Some(color), _) | (false, _, Some(color)) => {
+                        queue!(frame, SetForegroundColor(color))?
+                    }
+                    (true, None, _) => queue!(frame, SetForegroundColor(Color::Cyan))?,
+                    (false, _, None) => queue!(frame, SetForegroundColor(Color::DarkGrey))?,
+                }
+                write!(
+                    frame,
+                    "{}",
+                    truncate_to_width(status_line, max_width.saturating_sub(left_padding))
+                )?;
+                queue!(frame, SetForegroundColor(Color::Reset))?;
+            }
+            writeln!(frame)?;
             if let Some(bg) = bg_color {
-                execute!(stdout, SetBackgroundColor(bg))?;
+                queue!(frame, SetBackgroundColor(bg))?;
+            }
+            if let Some(color) = muted_color {
+                queue!(frame, SetForegroundColor(color))?;
+            } else {
+                queue!(
+                    frame,
+                    SetForegroundColor(Color::Green),
+                    SetAttribute(Attribute::Bold)
+                )?;
             }
-            writeln!(stdout, "{}", line)?;
+            let prompt = match &search {
+                Some(active) => {
+                    let found = active.found.and_then(|i| input_history.get(i));
+                    format!(
+                        "(reverse-i-search)'{}': {}",
+                        active.query,
+                        found.map_or("", String::as_str)
+                    )
+                }
+                None => format!("{}{}", prompt_text, with_cursor(&input, cursor_pos)),
+            };
+            for line in format_message(&prompt, max_width, left_padding, 0) {
+                if let Some(bg) = bg_color {
+                    queue!(frame, SetBackgroundColor(bg))?;
+                }
+                writeln!(frame, "{}", line)?;
+            }
+            queue!(frame, SetForegroundColor(Color::Reset))?;
+            stdout.write_all(&frame)?;
+            stdout.flush()?;
+            shown = (topic, status_line);
+            dirty = false;
         }
-        execute!(stdout, SetForegroundColor(Color::Reset))?;
-        stdout.flush()?;
 
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+        if event::poll(key_wait(drained))? {
+            let event = event::read()?;
+            dirty = true;
+            if let Event::Resize(cols, rows) = event {
+                (max_width, max_height) = layout(cols, rows, left_padding);
+            }
+            match event {
+                Event::FocusGained => focused = true,
+                Event::FocusLost => focused = false,
+                _ => {}
+            }
+            if let Event::Mouse(mouse) = &event {
+                // A couple of lines per wheel notch, clamped like PageUp/PageDown.
+                match mouse.kind {
+                    MouseEventKind::ScrollUp => {
+                        scroll_offset = clamp_scroll(
+                            scroll_offset + MOUSE_SCROLL_LINES,
+                            flat_messages.len(),
+                            max_height,
+                        );
+                    }
+                    MouseEventKind::ScrollDown => {
+                        scroll_offset = scroll_offset.saturating_sub(MOUSE_SCROLL_LINES);
+                    }
+                    _ => {}
+                }
+            }
+            if let Event::Paste(text) = &event {
+                // Pasted lines that are sent right away are messages, never commands,
+                // whatever they start with. One left on the input line asks first.
+                let lines = paste_lines(text);
+                if join_pastes || lines.len() <= 1 {
+                    let joined = lines.join(" ");
+                    input.insert_str(cursor_pos, &joined);
+                    if cursor_pos == 0 && joined.starts_with('/') {
+                        pasted_command = Some(input.clone());
+                    }
+                    cursor_pos += joined.len();
+                    input_history_index = None;
+                } else if lines.len() > paste_confirm_lines {
+                    push_line(
+                        &mut messages,
+                        format!(
+                            "*** Send {} pasted lines? Press y to send, any other key to cancel.",
+                            lines.len()
+                        )
+                        .into(),
+                    );
+                    pending_paste = Some(lines);
+                } else {
+                    send_paste(lines, &input_tx, &mut messages).await?;
+                }
+            }
+            if let Event::Key(key) = event {
+                last_key = Instant::now();
+                // Once the pasted line has been edited (or emptied), it's the user's own.
+                if pasted_command.as_ref() != Some(&input) {
+                    pasted_command = None;
+                }
+                if std::mem::take(&mut auto_away) {
+                    input_tx.send(InputCommand::AutoAway(false)).await?;
+                }
+                let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                if let Some(lines) = pending_paste.take() {
+                    if key.code == KeyCode::Char('y') {
+                        send_paste(lines, &input_tx, &mut messages).await?;
+                    } else {
+                        push_line(&mut messages, "*** Paste cancelled".to_string().into());
+                    }
+                    continue;
+                }
+                // While searching, keys edit the search rather than the input.
+                if let Some(mut active) = search.take() {
+                    match key.code {
+                        KeyCode::Enter => {
+                            if let Some(entry) = active.found.and_then(|i| input_history.get(i)) {
+                                input = entry.clone();
+                                cursor_pos = input.len();
+                                input_history_index = None;
+                            }
+                        }
+                        KeyCode::Esc => {}
+                        KeyCode::Char('r') if ctrl => {
+                            let before = active.found.unwrap_or(input_history.len());
+                            active.found = search_history(&input_history, &active.query, before)
+                                .or(active.found);
+                            search = Some(active);
+                        }
+                        KeyCode::Char(c) if !ctrl => {
+                            active.query.push(c);
+                            active.found =
+                                search_history(&input_history, &active.query, input_history.len());
+                            search = Some(active);
+                        }
+                        KeyCode::Backspace => {
+                            active.query.pop();
+                            active.found =
+                                search_history(&input_history, &active.query, input_history.len());
+                            search = Some(active);
+                        }
+                        _ => search = Some(active),
+                    }
+                    continue;
+                }
                 match key.code {
+                    KeyCode::Char('r') if ctrl => {
+                        search = Some(HistorySearch {
+                            query: String::new(),
+                            found: None,
+                        });
+                    }
+                    #[cfg(unix)]
+                    KeyCode::Char('z') if ctrl => {
+                        suspend(&mut stdout)?;
+                    }
+                    KeyCode::Char('l') if ctrl => {
+                        // Redraw from scratch: restore the terminal modes and pick up any missed resize.
+                        resume(&mut stdout, bg_color, mouse_scroll)?;
+                        let (cols, rows) = terminal::size().unwrap_or((80, 24));
+                        (max_width, max_height) = layout(cols, rows, left_padding);
+                    }
+                    KeyCode::Char('c') if ctrl => {
+                        input_tx.send(InputCommand::Quit(None)).await?;
+                        running = false;
+                    }
+                    KeyCode::Char('a') if ctrl => {
+                        cursor_pos = 0;
+                    }
+                    KeyCode::Char('e') if ctrl => {
+                        cursor_pos = input.len();
+                    }
+                    KeyCode::Char('u') if ctrl => {
+                        input.replace_range(..cursor_pos, "");
+                        cursor_pos = 0;
+                        input_history_index = None;
+                        if input.is_empty() {
+                            scroll_offset = scroll_offset.saturating_sub(paused_lines);
+                            paused_lines = 0;
+                        }
+                    }
+                    KeyCode::Char('k') if ctrl => {
+                        input.truncate(cursor_pos);
+                        input_history_index = None;
+                        if input.is_empty() {
+                            scroll_offset = scroll_offset.saturating_sub(paused_lines);
+                            paused_lines = 0;
+                        }
+                    }
+                    KeyCode::Char('w') if ctrl => {
+                        let start = previous_word_start(&input, cursor_pos);
+                        input.replace_range(start..cursor_pos, "");
+                        cursor_pos = start;
+                        input_history_index = None;
+                        if input.is_empty() {
+                            scroll_offset = scroll_offset.saturating_sub(paused_lines);
+                            paused_lines = 0;
+                        }
+                    }
                     KeyCode::Char(c) => {
-                        input.push(c);
+                        input.insert(cursor_pos, c);
+                        cursor_pos += c.len_utf8();
                         input_history_index = None;
                     }
+                    KeyCode::Left => {
+                        if let Some(c) = input[..cursor_pos].chars().next_back() {
+                            cursor_pos -= c.len_utf8();
+                        }
+                    }
+                    KeyCode::Right => {
+                        if let Some(c) = input[cursor_pos..].chars().next() {
+                            cursor_pos += c.len_utf8();
+                        }
+                    }
+                    // Ctrl+Home/End move through the buffer; plain Home/End edit the line.
+                    KeyCode::Home if ctrl => {
+                        scroll_offset = clamp_scroll(usize::MAX, flat_messages.len(), max_height);
+                    }
+                    KeyCode::End if ctrl => {
+                        scroll_offset = 0;
+                        paused_lines = 0;
+                    }
+                    KeyCode::Home => cursor_pos = 0,
+                    KeyCode::End => cursor_pos = input.len(),
+                    KeyCode::Tab if shortcode_at(&input, cursor_pos).is_some() => {
+                        let Some((start, partial)) = shortcode_at(&input, cursor_pos) else {
+                            continue;
+                        };
+                        let candidates: Vec<&String> = emoji_aliases
+                            .iter()
+                            .filter(|a| a.starts_with(partial))
+                            .collect();
+                        match candidates.as_slice() {
+                            [] => {}
+                            [only] => {
+                                let code = format!(":{}:", only);
+                                input.replace_range(start..cursor_pos, &code);
+                                cursor_pos = start + code.len();
+                            }
+                            many => {
+                                let codes: Vec<String> =
+                                    many.iter().map(|a| format!(":{}:", a)).collect();
+                                push_line(&mut messages, codes.join("  ").into());
+                            }
+                        }
+                    }
+                    KeyCode::Tab if input.starts_with('/') && !input.contains(' ') => {
+                        let candidates: Vec<&str> = COMMANDS
+                            .iter()
+                            .map(CommandHelp::name)
+                            .filter(|c| c.starts_with(input.as_str()))
+                            .collect();
+                        match candidates.as_slice() {
+                            [] => {}
+                            [only] => {
+                                input = format!("{} ", only);
+                                cursor_pos = input.len();
+                            }
+                            many => {
+                                push_line(&mut messages, many.join("  ").into());
+                            }
+                        }
+                    }
                     KeyCode::Backspace => {
-                        input.pop();
+                        if let Some(c) = input[..cursor_pos].chars().next_back() {
+                            cursor_pos -= c.len_utf8();
+                            input.remove(cursor_pos);
+                        }
                         input_history_index = None;
+                        if input.is_empty() {
+                            scroll_offset = scroll_offset.saturating_sub(paused_lines);
+                            paused_lines = 0;
+                        }
                     }
                     KeyCode::Enter => {
-                        if !input.trim().is_empty() {
+                        // Copied chat text can start with `/`; a pasted command
+                        // only runs once Enter has been pressed a second time.
+                        if pasted_command.take().is_some() {
+                            push_line(
+                                &mut messages,
+                                "*** The pasted line is a command. Press Enter again to run it."
+                                    .to_string()
+                                    .into(),
+                            );
+                            continue;
+                        }
+                        if !input.trim().is_empty() && input_history.last() != Some(&input) {
                             input_history.push(input.clone());
                         }
                         input_history_index = None;
                         scroll_offset = 0;
+                        paused_lines = 0;
 
-                        if input.starts_with('/') {
-                            let mut parts = input.trim().splitn(2, ' ');
-                            let cmd = parts.next().unwrap_or("");
-                            let arg = parts.next().unwrap_or("");
-
-                            match cmd {
-                                "/connect" => {
-                                    let mut args = arg.split_whitespace();
-                                    let server = args.next().unwrap_or("").to_string();
-
-                                    let config = config.clone();
-                                    let port = config
-                                        .as_ref()
-                                        .and_then(|c| c.irc.as_ref()?.port)
-                                        .unwrap_or(6697);
-
-                                    let nick = config
-                                        .as_ref()
-                                        .and_then(|c| c.irc.as_ref()?.nick.clone())
-                                        .unwrap_or_else(|| "meow".to_string());
-
-                                    let tls = config
-                                        .as_ref()
-                                        .and_then(|c| c.irc.as_ref()?.tls)
-                                        .unwrap_or(true);
-
-                                    input_tx
-                                        .send(InputCommand::Connect {
-                                            server,
-                                            port,
-                                            nick,
-                                            tls,
-                                        })
-                                        .await?;
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
+                        match parse_input(&input, config.as_ref()) {
+                            Parsed::Send(commands, echo) => {
+                                for command in commands {
+                                    match &command {
+                                        InputCommand::Connect { nick, .. } => {
+                                            own_nick = nick.clone()
+                                        }
+                                        InputCommand::Quit(_) => running = false,
+                                        _ => {}
+                                    }
+                                    input_tx.send(command).await?;
                                 }
-                                "/join" => {
-                                    input_tx
-                                        .send(InputCommand::JoinChannel(arg.to_string()))
-                                        .await?;
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
+                                if let Some(echo) = echo {
+                                    push_line(&mut messages, format!("You: {}", echo).into());
                                 }
-                                "/part" => {
-                                    input_tx
-                                        .send(InputCommand::PartChannel(arg.to_string()))
-                                        .await?;
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
+                            }
+                            Parsed::Invalid(error, echo) => {
+                                push_line(&mut messages, error.into());
+                                if let Some(echo) = echo {
+                                    push_line(&mut messages, format!("You: {}", echo).into());
                                 }
-                                "/msg" => {
-                                    let mut msg_parts = arg.splitn(2, ' ');
-                                    if let (Some(target), Some(message)) =
-                                        (msg_parts.next(), msg_parts.next())
-                                    {
-                                        let prefixed_message = prefix_message(message);
-                                        input_tx
-                                            .send(InputCommand::SendMessage {
-                                                target: target.to_string(),
-                                                message: prefixed_message.clone(), // Send the prefixed message
-                                            })
-                                            .await?;
-                                        let user_msg =
-                                            format!("You: /msg {} {}", target, prefixed_message); // Display the command with prefixed message
-                                        messages.push_back(format_message(
-                                            &user_msg,
-                                            max_width,
-                                            left_padding,
-                                        ));
-                                    } else {
-                                        let user_msg = format!("You: {}", input); // Display original input if /msg format is wrong
-                                        messages.push_back(format_message(
-                                            &user_msg,
-                                            max_width,
-                                            left_padding,
-                                        ));
+                            }
+                            Parsed::Local { cmd, arg, line } => {
+                                let user_msg = format!("You: {}", line); // Display command as is
+                                match cmd.as_str() {
+                                    "/clear" => {
+                                        // Local only: nothing goes to the server.
+                                        messages.clear();
+                                        scroll_offset = 0;
+                                        paused_lines = 0;
+                                        push_line(
+                                            &mut messages,
+                                            "*** Buffer cleared".to_string().into(),
+                                        );
                                     }
-                                }
-                                "/quit" => {
-                                    input_tx.send(InputCommand::Quit).await?;
-                                    running = false;
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
-                                }
-                                "/help" => {
-                                    let help_lines = [
-                                        "╭───────────────────────────────────────────────╮",
-                                        "│                   Help Menu                  │",
-                                        "├───────────────────────────────────────────────┤",
-                                        "│ /connect <server> [port] [nick] [tls]        │",
-                                        "│ /join <channel>                              │",
-                                        "│ /part <channel>                              │",
-                                        "│ /msg <target> <message>                      │",
-                                        "│ /quit                                        │",
-                                        "╰───────────────────────────────────────────────╯",
-                                    ];
-                                    for line in help_lines {
-                                        messages.push_back(format_message(
-                                            line,
-                                            max_width,
-                                            left_padding,
-                                        ));
+                                    "/filter" => {
+                                        let report =
+                                            match arg.split_whitespace().collect::<Vec<_>>()[..] {
+                                                ["joins", "on"] => {
+                                                    hide_joins = true;
+                                                    "*** Hiding joins, parts and quits"
+                                                }
+                                                ["joins", "off"] => {
+                                                    hide_joins = false;
+                                                    "*** Showing joins, parts and quits"
+                                                }
+                                                _ => "Usage: /filter joins on|off",
+                                            };
+                                        push_line(&mut messages, user_msg.into());
+                                        push_line(&mut messages, report.to_string().into());
+                                    }
+                                    "/colors" => {
+                                        let report = match arg.trim() {
+                                            "on" => {
+                                                strip_colors = false;
+                                                "*** Showing colors and formatting in new messages"
+                                            }
+                                            "off" => {
+                                                strip_colors = true;
+                                                "*** Stripping colors and formatting from new messages"
+                                            }
+                                            _ => "Usage: /colors on|off",
+                                        };
+                                        push_line(&mut messages, user_msg.into());
+                                        push_line(&mut messages, report.to_string().into());
+                                    }
+                                    "/reload" => reload = true,
+                                    _ if !arg.is_empty() => {
+                                        let help =
+                                            command_help(&arg).map(|h| deco(&h)).unwrap_or_else(
+                                                || format!("Unknown command: {} (see /help)", arg),
+                                            );
+                                        push_line(&mut messages, help.into());
+                                        push_line(&mut messages, user_msg.into());
+                                    }
+                                    _ => {
+                                        for line in help_box() {
+                                            push_line(&mut messages, deco(&line).into());
+                                        }
+                                        push_line(&mut messages, user_msg.into());
                                     }
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
-                                }
-                                _ => {
-                                    let unknown = format!("Unknown command: {}", cmd);
-                                    messages.push_back(format_message(
-                                        &unknown,
-                                        max_width,
-                                        left_padding,
-                                    ));
-                                    let user_msg = format!("You: {}", input); // Display command as is
-                                    messages.push_back(format_message(
-                                        &user_msg,
-                                        max_width,
-                                        left_padding,
-                                    ));
                                 }
                             }
-                        } else {
-                            // This is for non-command messages
-                            let prefixed_input = prefix_message(&input);
-                            let user_msg = format!("You: {}", prefixed_input); // Apply prefixing for display
-                            messages.push_back(format_message(&user_msg, max_width, left_padding));
-                            input_tx
-                                .send(InputCommand::SendPlainMessage(prefixed_input))
-                                .await?; // Send prefixed message to IRC
                         }
 
                         input.clear();
+                        cursor_pos = 0;
                     }
                     KeyCode::Esc => {
-                        input_tx.send(InputCommand::Quit).await?;
+                        input_tx.send(InputCommand::Quit(None)).await?;
                         running = false;
                     }
                     KeyCode::PageUp => {
-                        scroll_offset += 5;
-                        if scroll_offset > flat_messages.len().saturating_sub(1) {
-                            scroll_offset = flat_messages.len().saturating_sub(1);
-                        }
+                        scroll_offset = clamp_scroll(
+                            scroll_offset + scroll_step.lines(max_height),
+                            flat_messages.len(),
+                            max_height,
+                        );
                     }
                     KeyCode::PageDown => {
-                        scroll_offset = scroll_offset.saturating_sub(5);
+                        scroll_offset = scroll_offset.saturating_sub(scroll_step.lines(max_height));
                     }
                     KeyCode::Up => {
                         if input_history.is_empty() {
@@ -442,6 +2251,7 @@ pub async fn run_ui(
                         if let Some(i) = input_history_index {
                             if let Some(entry) = input_history.get(i) {
                                 input = entry.clone();
+                                cursor_pos = input.len();
                             }
                         }
                     }
@@ -454,11 +2264,13 @@ pub async fn run_ui(
                                 input_history_index = Some(i + 1);
                                 if let Some(entry) = input_history.get(i + 1) {
                                     input = entry.clone();
+                                    cursor_pos = input.len();
                                 }
                             }
                             _ => {
                                 input_history_index = None;
                                 input.clear();
+                                cursor_pos = 0;
                             }
                         }
                     }
@@ -474,6 +2286,209 @@ pub async fn run_ui(
         SetBackgroundColor(Color::Reset),
         SetForegroundColor(Color::Reset)
     )?;
+    let _ = execute!(stdout, DisableBracketedPaste, DisableMouseCapture);
     disable_raw_mode()?;
+    // Not being able to save history is no reason to fail the exit.
+    let _ = history::save(&input_history, history_size);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::irc_client::normalize_host;
+
+    #[test]
+    fn buffer_stays_at_the_limit() {
+        let mut messages = VecDeque::new();
+        for i in 0..200 {
+            push_line(&mut messages, format!("line {}", i).into());
+        }
+        assert_eq!(messages.len(), BUFFER_LIMIT);
+        assert_eq!(messages.front().map(|l| l.text.as_str()), Some("line 100"));
+        assert_eq!(messages.back().map(|l| l.text.as_str()), Some("line 199"));
+    }
+
+    #[test]
+    fn a_flood_is_taken_a_frame_at_a_time() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1000);
+        for i in 0..1000 {
+            tx.try_send(Message::from(format!("line {}", i))).unwrap();
+        }
+        let lines = take_lines(&mut rx);
+        assert_eq!(lines.len(), DRAIN_PER_FRAME);
+        assert_eq!(lines[0].text, "line 0");
+        assert_eq!(rx.len(), 1000 - DRAIN_PER_FRAME);
+        assert_eq!(take_lines(&mut rx)[0].text, "line 50");
+    }
+
+    #[test]
+    fn keys_are_read_in_the_same_frame_as_a_flood() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1000);
+        for i in 0..1000 {
+            tx.try_send(Message::from(format!("line {}", i))).unwrap();
+        }
+        // A full drain leaves lines waiting: the key check mustn't block.
+        assert_eq!(key_wait(take_lines(&mut rx).len()), Duration::ZERO);
+        // Once the backlog is gone, the frame idles waiting for a key.
+        while take_lines(&mut rx).len() == DRAIN_PER_FRAME {}
+        assert!(key_wait(take_lines(&mut rx).len()) > Duration::ZERO);
+    }
+
+    const URL: &str = "https://example.com/a/very/long/path/that/keeps/going/and/going";
+
+    #[test]
+    fn wrapped_rows_fit_the_width() {
+        let text = format!("see {} for details, or {} again later today", URL, URL);
+        for indent in [0, 4] {
+            for row in wrap_text(&text, 20, indent) {
+                assert!(row.width() <= 20, "{:?} is wider than 20", row);
+            }
+        }
+    }
+
+    #[test]
+    fn long_urls_are_split_and_words_are_not() {
+        let text = format!("see {} for the release notes", URL);
+        let rows = wrap_text(&text, 20, 0);
+        assert!(rows.len() > 3);
+        // The URL is spread over several rows but nothing of it is lost.
+        let joined: String = rows.iter().map(|r| r.trim()).collect();
+        assert!(joined.contains(URL));
+        assert!(!rows.iter().any(|r| r.trim() == URL));
+        for word in ["see", "for", "the", "release", "notes"] {
+            assert!(
+                rows.iter().any(|r| r.split(' ').any(|w| w == word)),
+                "{:?} was split in {:?}",
+                word,
+                rows
+            );
+        }
+    }
+
+    #[test]
+    fn scrolling_stays_inside_the_buffer() {
+        // Empty, or shorter than the view: there is nothing to scroll back to.
+        assert_eq!(clamp_scroll(0, 0, 20), 0);
+        assert_eq!(clamp_scroll(5, 0, 20), 0);
+        assert_eq!(clamp_scroll(5, 10, 20), 0);
+        // Past the top stops with the first line at the top of the view.
+        assert_eq!(clamp_scroll(500, 100, 20), 80);
+        assert_eq!(clamp_scroll(30, 100, 20), 30);
+        // A resize that shrinks the view keeps the offset; a taller one has
+        // less to scroll through.
+        assert_eq!(clamp_scroll(80, 100, 10), 80);
+        assert_eq!(clamp_scroll(80, 100, 50), 50);
+    }
+
+    /// The server (as the IRC task will dial it) and port `/connect` picks.
+    fn connect_to(arg: &str) -> Option<(String, u16)> {
+        match parse_connect(arg, None)? {
+            InputCommand::Connect { server, port, .. } => {
+                Some((normalize_host(&server).to_string(), port))
+            }
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ipv6_literals_connect_to_the_bare_address() {
+        let ipv6 = |addr: &str, port| Some((addr.to_string(), port));
+        assert_eq!(connect_to("[::1]"), ipv6("::1", 6697));
+        assert_eq!(connect_to("::1"), ipv6("::1", 6697));
+        assert_eq!(connect_to("::1 6667 me false"), ipv6("::1", 6667));
+        assert_eq!(connect_to("[2001:db8::1]:6697"), ipv6("2001:db8::1", 6697));
+        assert_eq!(connect_to("[2001:db8::1]:7000"), ipv6("2001:db8::1", 7000));
+        assert_eq!(connect_to("2001:db8::1 6697"), ipv6("2001:db8::1", 6697));
+        assert_eq!(
+            connect_to("irc.example.net:6667"),
+            Some(("irc.example.net".to_string(), 6667))
+        );
+        assert_eq!(connect_to(""), None);
+    }
+
+    #[test]
+    fn connect_echo_masks_only_the_password() {
+        assert_eq!(connect_echo(""), "/connect");
+        assert_eq!(
+            connect_echo("irc.example.net 6697 me true"),
+            "/connect irc.example.net 6697 me true"
+        );
+        // The password also appearing earlier in the line doesn't matter.
+        assert_eq!(
+            connect_echo("true.example.net 6697 true true true"),
+            "/connect true.example.net 6697 true true ********"
+        );
+        assert_eq!(
+            connect_echo("me.example.net  6697 me false me"),
+            "/connect me.example.net 6697 me false ********"
+        );
+    }
+
+    /// Whether `line`, rendered as usual, highlights for a user called mycat.
+    fn highlights(line: Message) -> bool {
+        let mut nick_colors = NickColors::new(&UserConfig::default());
+        let rendered = render(&line, &mut nick_colors, None, false);
+        mark_mentions(&line, &rendered, "mycat", "1;33", &[], false)
+            .1
+            .is_some()
+    }
+
+    #[test]
+    fn mentions_follow_the_sender_not_the_text() {
+        let privmsg = |sender: &str, text: &str| {
+            Message::new(MessageKind::Privmsg, text).with_sender(sender, "#meow")
+        };
+        assert!(highlights(privmsg("Youri", "hi mycat")));
+        assert!(highlights(privmsg("alice", "<You->#meow> mycat")));
+        assert!(!highlights(privmsg("alice", "hi everyone")));
+        assert!(!highlights(privmsg("mycat", "I am mycat")));
+        assert!(highlights(
+            Message::new(MessageKind::Action, "waves at mycat").with_sender("alice", "#meow")
+        ));
+        // Nor do our own lines, whatever nick the server knows us by.
+        assert!(!highlights(
+            Message::new(MessageKind::Privmsg, "hi mycat")
+                .with_sender("mycat_", "#meow")
+                .outgoing()
+        ));
+    }
+
+    #[test]
+    fn wildcard_highlights_match_whole_words() {
+        let text = "Deploying now, redeploy later";
+        assert_eq!(word_matches(text, "deploy*"), vec![(0, 9)]);
+        assert_eq!(word_matches(text, "*deploy"), vec![(15, 23)]);
+        assert!(word_matches(text, "deploy").is_empty());
+    }
+
+    #[test]
+    fn input_is_parsed_the_same_everywhere() {
+        let config: UserConfig = toml::from_str("[aliases]\nk = \"/kick #meow\"").unwrap();
+        let config = Some(&config);
+        match parse_input("/k bob flooding", config) {
+            Parsed::Send(commands, _) => assert!(matches!(
+                &commands[..],
+                [InputCommand::Kick { channel: Some(channel), nick, reason: Some(reason) }]
+                    if channel == "#meow" && nick == "bob" && reason == "flooding"
+            )),
+            _ => panic!("alias not expanded"),
+        }
+        match parse_input("/join #secret hunter2", config) {
+            Parsed::Send(commands, echo) => {
+                assert!(matches!(
+                    &commands[..],
+                    [InputCommand::JoinChannel(channel, Some(key))]
+                        if channel == "#secret" && key == "hunter2"
+                ));
+                assert_eq!(echo.as_deref(), Some("/join #secret ********"));
+            }
+            _ => panic!("/join not parsed"),
+        }
+        assert!(matches!(parse_input("/op", config), Parsed::Invalid(..)));
+        assert!(matches!(
+            parse_input("/clear", config),
+            Parsed::Local { .. }
+        ));
+    }
+}
@@ -0,0 +1,25 @@
+/// Whether `text` matches `mask`, where `*` stands for any run of
+/// characters and `?` for any one. Case doesn't matter, as with nicks.
+pub fn wildcard_match(mask: &str, text: &str) -> bool {
+    let mask: Vec<char> = mask.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut m, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None; // where the last `*` was, and the text position it matched up to
+    while t < text.len() {
+        if m < mask.len() && (mask[m] == '?' || mask[m] == text[t]) {
+            m += 1;
+            t += 1;
+        } else if m < mask.len() && mask[m] == '*' {
+            star = Some((m, t));
+            m += 1;
+        } else if let Some((star_m, star_t)) = star {
+            // Let the last `*` swallow one more character and try again.
+            m = star_m + 1;
+            t = star_t + 1;
+            star = Some((star_m, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    mask[m..].iter().all(|&c| c == '*')
+}